@@ -54,6 +54,24 @@ impl embedded_io_async::ErrorType for FakeSerial {
     type Error = FSError;
 }
 
+impl r503::BaudRateControl for FakeSerial {
+    async fn set_baud_rate(&mut self, baud_rate: r503::constants::BaudRate) -> Result<(), Self::Error> {
+        self.set_baudrate(baud_rate.bits_per_second())
+            .await
+            .map_err(|_| FSError::Other)
+    }
+}
+
+/// Delays for the driver's timeouts, using the tokio timer
+#[derive(Clone, Copy)]
+pub struct TokioDelay;
+
+impl embedded_hal_async::delay::DelayNs for TokioDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        tokio::time::sleep(Duration::from_nanos(ns.into())).await
+    }
+}
+
 impl embedded_io_async::Read for FakeSerial {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         if !self.in_queue.is_empty() {
@@ -1,17 +1,13 @@
-use embedded_io_async::Read;
-use impls::FakeSerial;
+use impls::{FakeSerial, TokioDelay};
 use postcard_rpc::Key;
 use postcard_schema::Schema;
 use poststation_sdk::connect;
 use r503::{
-    auto::{AutoEnroll, AutoEnrollConfig, AutoIdentify, AutoIdentifyConfig}, constants::{AutoIdentCount, CharBufferId, ConfirmationCode, IdentifySafety, IndexTableIdx}, Error, LoadCharRequest, R503
+    auto::{AutoEnroll, AutoEnrollConfig, AutoIdentify, AutoIdentifyConfig, AutoIdentifyRange}, constants::{AutoIdentCount, BaudRate, CharBufferId, ConfirmationCode, IdentifySafety, ModelId}, Error, LoadCharRequest, StoreRequest, R503
 };
 use serde::{Deserialize, Serialize};
 use std::{fs::File, io::{Read as _, Write}, net::SocketAddr, num::ParseIntError, time::Duration};
-use tokio::{
-    select,
-    time::{sleep, timeout},
-};
+use tokio::{select, time::sleep};
 
 const TEMPLATE_KEY: Key = Key::for_path::<TemplateExport>("template export");
 
@@ -28,6 +24,8 @@ pub struct TemplateExport {
 
 pub mod impls;
 
+type Sensor = R503<TokioDelay>;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tokio::task::spawn(inner_main()).await??;
@@ -42,7 +40,7 @@ async fn inner_main() -> anyhow::Result<()> {
     let mut serial = FakeSerial::new(&client, serial).await.unwrap();
     serial.set_baudrate(57_600).await.unwrap();
 
-    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(TokioDelay);
     let rand = r5.get_rand_code(&mut serial).await.unwrap();
     println!("Rand said: {rand:08X}");
     let r5 = &r5;
@@ -54,19 +52,20 @@ async fn inner_main() -> anyhow::Result<()> {
 
         let line = read_line().await;
 
-        // Drain any extra bytes
-        loop {
-            let mut buf = [0u8; 64];
-            match timeout(Duration::from_millis(100), serial.read(&mut buf)).await {
-                Ok(_) => {}
-                Err(_) => break,
-            }
-        }
-
         let tline = line.trim();
         let words = tline.split_whitespace().collect::<Vec<_>>();
         let res = match words.as_slice() {
             ["empty"] => r5.empty(serial).await,
+            ["baud", bps] => {
+                let Some(rate) = hex_or_dec::<u32>(bps).and_then(|bps| {
+                    let rate = BaudRate::try_from((bps / 9600) as u8).ok()?;
+                    (rate.bits_per_second() == bps).then_some(rate)
+                }) else {
+                    println!("Bad baud rate");
+                    continue 'repl;
+                };
+                r5.reconfigure_baud_rate(serial, rate).await
+            }
             ["idx", "table", "read"] => read_idx_table(r5, serial).await,
             ["auto", "enroll"] => auto_enroll(r5, serial).await,
             ["auto", "enroll", "loop"] => {
@@ -76,12 +75,16 @@ async fn inner_main() -> anyhow::Result<()> {
                         sleep(Duration::from_secs(3)).await;
                     }
                 };
-                // run until user presses enter
-                select! {
-                    _ = fut => {}
-                    _ = read_line() => {}
+                // run until user presses enter, then stop whatever is running
+                let cancelled = select! {
+                    _ = fut => false,
+                    _ = read_line() => true,
+                };
+                if cancelled {
+                    r5.cancel(serial).await
+                } else {
+                    Ok(())
                 }
-                Ok(())
             }
             ["auto", "identify", grade, start, end, count] => {
                 let Some(grade) =
@@ -90,12 +93,8 @@ async fn inner_main() -> anyhow::Result<()> {
                     println!("Bad grade");
                     continue 'repl;
                 };
-                let Some(start_pos) = hex_or_dec(start) else {
-                    println!("Bad start");
-                    continue 'repl;
-                };
-                let Some(steps_or_end) = hex_or_dec(end) else {
-                    println!("Bad end");
+                let Some(range) = parse_identify_range(r5, serial, start, end).await else {
+                    println!("Bad range");
                     continue 'repl;
                 };
                 let Some(err_count) = hex_or_dec::<u8>(count).map(Into::into) else {
@@ -104,8 +103,7 @@ async fn inner_main() -> anyhow::Result<()> {
                 };
                 let cfg = AutoIdentifyConfig {
                     grade,
-                    start_pos,
-                    steps_or_end,
+                    range,
                     return_status: true,
                     err_count,
                 };
@@ -118,12 +116,8 @@ async fn inner_main() -> anyhow::Result<()> {
                     println!("Bad grade");
                     continue 'repl;
                 };
-                let Some(start_pos) = hex_or_dec(start) else {
-                    println!("Bad start");
-                    continue 'repl;
-                };
-                let Some(steps_or_end) = hex_or_dec(end) else {
-                    println!("Bad end");
+                let Some(range) = parse_identify_range(r5, serial, start, end).await else {
+                    println!("Bad range");
                     continue 'repl;
                 };
                 let Some(err_count) = hex_or_dec::<u8>(count).map(Into::into) else {
@@ -132,8 +126,7 @@ async fn inner_main() -> anyhow::Result<()> {
                 };
                 let cfg = AutoIdentifyConfig {
                     grade,
-                    start_pos,
-                    steps_or_end,
+                    range,
                     return_status: true,
                     err_count,
                 };
@@ -150,17 +143,25 @@ async fn inner_main() -> anyhow::Result<()> {
                         sleep(Duration::from_secs(3)).await;
                     }
                 };
-                // run until user presses enter
-                select! {
-                    _ = fut => {}
-                    _ = read_line() => {}
+                // run until user presses enter, then stop whatever is running
+                let cancelled = select! {
+                    _ = fut => false,
+                    _ = read_line() => true,
+                };
+                if cancelled {
+                    r5.cancel(serial).await
+                } else {
+                    Ok(())
                 }
-                Ok(())
             }
             ["dump", "templates", path] => {
                 dump_templates(r5, serial, path).await.unwrap();
                 Ok(())
             },
+            ["restore", "templates", path] => {
+                restore_templates(r5, serial, path).await.unwrap();
+                Ok(())
+            },
             ["debugload", "templates", path] => {
                 debugload_templates(path).unwrap();
                 Ok(())
@@ -172,12 +173,16 @@ async fn inner_main() -> anyhow::Result<()> {
         };
         match res {
             Ok(()) => println!("Success"),
-            Err(e) => println!("Error: {e:?}"),
+            Err(e) => println!("Error: {e}"),
+        }
+        let skipped = r5.take_skipped_bytes();
+        if skipped != 0 {
+            println!("Skipped {skipped} bytes of line noise");
         }
     }
 }
 
-fn debugload_templates(path: &str) -> Result<(), r503::Error<FakeSerial>> {
+fn load_templates(path: &str) -> TemplateExport {
     let mut buf = vec![];
     let mut f = File::open(path).unwrap();
     f.read_to_end(&mut buf).unwrap();
@@ -185,7 +190,27 @@ fn debugload_templates(path: &str) -> Result<(), r503::Error<FakeSerial>> {
     if now != TEMPLATE_KEY {
         panic!("Wrong header!");
     }
-    let templates = postcard::from_bytes::<TemplateExport>(later).unwrap();
+    postcard::from_bytes::<TemplateExport>(later).unwrap()
+}
+
+async fn restore_templates(r5: &Sensor, serial: &mut FakeSerial, path: &str) -> Result<(), r503::Error<FakeSerial>> {
+    let templates = load_templates(path);
+    let params = r5.read_system_parameter(serial).await?;
+    for temp in templates.templates {
+        let Some(model_id) = params.model_id(temp.idx) else {
+            println!("Skipping template {}, past the end of the library", temp.idx);
+            continue;
+        };
+        println!("Restoring template {model_id}");
+        r5.download_template(serial, CharBufferId::One, &temp.data).await?;
+        r5.store(serial, StoreRequest { char_buffer: CharBufferId::One, model_id }).await?;
+    }
+    println!("Restored from '{path}'");
+    Ok(())
+}
+
+fn debugload_templates(path: &str) -> Result<(), r503::Error<FakeSerial>> {
+    let templates = load_templates(path);
     for temp in templates.templates {
         println!("# Template {}", temp.idx);
         println!();
@@ -200,20 +225,9 @@ fn debugload_templates(path: &str) -> Result<(), r503::Error<FakeSerial>> {
     Ok(())
 }
 
-async fn dump_templates<'a>(r5: &R503, serial: &mut FakeSerial, path: &str) -> Result<(), r503::Error<FakeSerial>> {
-    let mut templates: Vec<u16> = vec![];
-    for i in 0..4 {
-        let idx = IndexTableIdx::try_from(i).unwrap();
-        let val = r5.read_idx_table(serial, idx).await?;
-        for (j, mut by) in val.into_iter().enumerate() {
-            for k in 0..8 {
-                if by & 0x01 != 0 {
-                    templates.push((i as u16 * 256u16) + (j * 8) as u16 + k);
-                }
-                by >>= 1;
-            }
-        }
-    }
+async fn dump_templates<'a>(r5: &Sensor, serial: &mut FakeSerial, path: &str) -> Result<(), r503::Error<FakeSerial>> {
+    let index = r5.read_template_index(serial).await?;
+    let templates: Vec<ModelId> = index.iter_occupied().collect();
 
     let mut out = vec![];
     let mut buf = vec![0u8; 512];
@@ -225,9 +239,9 @@ async fn dump_templates<'a>(r5: &R503, serial: &mut FakeSerial, path: &str) -> R
         r5.upload_template(serial, CharBufferId::One).await?;
         let used = r5.stream_image(serial, &mut buf).await?;
         if used != 512 {
-            return Err(r503::Error::IncorrectData);
+            return Err(r503::Error::WrongSize { expected: 512, actual: used });
         }
-        out.push(SingleTemplate { idx: template, data: buf.clone() });
+        out.push(SingleTemplate { idx: template.get(), data: buf.clone() });
     }
 
     let all_templates = TemplateExport { templates: out };
@@ -241,44 +255,58 @@ async fn dump_templates<'a>(r5: &R503, serial: &mut FakeSerial, path: &str) -> R
     Ok(())
 }
 
-async fn read_idx_table(r5: &R503, serial: &mut FakeSerial) -> Result<(), r503::Error<FakeSerial>> {
-    for i in 0..4 {
-        println!("# {i}");
-        let idx = IndexTableIdx::try_from(i).unwrap();
-        let val = r5.read_idx_table(serial, idx).await?;
-        for ch in val.chunks(4) {
-            for by in ch {
-                let mut by = *by;
-                for _ in 0..8 {
-                    if by & 0x01 != 0 {
-                        print!("F");
-                    } else {
-                        print!("_");
-                    }
-                    by >>= 1;
-                }
-                print!(" ");
-            }
+async fn read_idx_table(r5: &Sensor, serial: &mut FakeSerial) -> Result<(), r503::Error<FakeSerial>> {
+    let index = r5.read_template_index(serial).await?;
+    for id in 0..index.capacity() {
+        if id % 256 == 0 {
+            println!("# {}", id / 256);
+        }
+        let occupied = ModelId::new(id, index.capacity()).is_some_and(|m| index.is_occupied(m));
+        print!("{}", if occupied { "F" } else { "_" });
+        if id % 8 == 7 {
+            print!(" ");
+        }
+        if id % 32 == 31 {
             println!();
         }
-        println!();
     }
+    println!();
+    println!("{} of {} slots used", index.count(), index.capacity());
     Ok(())
 }
 
+/// Parse the ends of an AutoIdentify range, checked against the library size
+async fn parse_identify_range(
+    r5: &Sensor,
+    serial: &mut FakeSerial,
+    start: &str,
+    end: &str,
+) -> Option<AutoIdentifyRange> {
+    let params = match r5.read_system_parameter(serial).await {
+        Ok(params) => params,
+        Err(e) => {
+            println!("{e:?}");
+            return None;
+        }
+    };
+    let start = hex_or_dec(start).and_then(|id| params.model_id(id))?;
+    let end = hex_or_dec(end).and_then(|id| params.model_id(id))?;
+    AutoIdentifyRange::new(start, end)
+}
+
 async fn auto_identify(
-    r5: &R503,
+    r5: &Sensor,
     serial: &mut FakeSerial,
     cfg: AutoIdentifyConfig,
 ) -> Result<(), r503::Error<FakeSerial>> {
     // In case there are any stale updates (for example: the sensor replied twice
     // but we only heard the first response), drain any pending data on the UART before
     // starting a new transaction
-    let dumped = read_dump(serial).await?;
+    let dumped = r5.resync(serial).await?;
     if dumped != 0 {
         println!("Dumped {dumped} bytes");
     }
-    let mut identify = AutoIdentify::new(r5.address(), serial);
+    let mut identify = AutoIdentify::new(r5.address(), serial).with_delay(TokioDelay);
     let err_count = cfg.err_count;
     identify.start(cfg).await?;
     println!("START AUTO Identify");
@@ -307,55 +335,12 @@ async fn auto_identify(
     Ok(())
 }
 
-async fn read_dump(serial: &mut FakeSerial) -> Result<usize, r503::Error<FakeSerial>> {
-    let mut buf = [0u8; 64];
-    let mut ct = 0;
-    loop {
-
-        match timeout(Duration::from_millis(100), serial.read(&mut buf)).await {
-            Ok(Ok(n)) => {
-                ct += n;
-            }
-            Ok(Err(e)) => return Err(r503::Error::Wire(e)),
-            Err(_) => return Ok(ct),
-        }
-    }
-}
-
-async fn auto_enroll(r5: &R503, serial: &mut FakeSerial) -> Result<(), r503::Error<FakeSerial>> {
-    let mut enroll = AutoEnroll::new(r5.address(), serial);
+async fn auto_enroll(r5: &Sensor, serial: &mut FakeSerial) -> Result<(), r503::Error<FakeSerial>> {
+    let mut enroll = AutoEnroll::new(r5.address(), serial).with_delay(TokioDelay);
     println!("START AUTO ENROLL");
-    enroll.start(AutoEnrollConfig::default()).await?;
-    println!("wait_collect_image1...");
-    enroll.wait_collect_image1().await?;
-    println!("wait_generate_feature1...");
-    enroll.wait_generate_feature1().await?;
-    println!("wait_collect_image2...");
-    enroll.wait_collect_image2().await?;
-    println!("wait_generate_feature2...");
-    enroll.wait_generate_feature2().await?;
-    println!("wait_collect_image3...");
-    enroll.wait_collect_image3().await?;
-    println!("wait_generate_feature3...");
-    enroll.wait_generate_feature3().await?;
-    println!("wait_collect_image4...");
-    enroll.wait_collect_image4().await?;
-    println!("wait_generate_feature4...");
-    enroll.wait_generate_feature4().await?;
-    println!("wait_collect_image5...");
-    enroll.wait_collect_image5().await?;
-    println!("wait_generate_feature5...");
-    enroll.wait_generate_feature5().await?;
-    println!("wait_collect_image6...");
-    enroll.wait_collect_image6().await?;
-    println!("wait_generate_feature6...");
-    enroll.wait_generate_feature6().await?;
-    println!("wait_repeatfingerprint...");
-    enroll.wait_repeatfingerprint().await?;
-    println!("wait_merge_feature...");
-    enroll.wait_merge_feature().await?;
-    println!("wait_storage_template...");
-    let id = enroll.wait_storage_template().await?;
+    let id = enroll
+        .run(AutoEnrollConfig::default(), |event| println!("{event:?}"))
+        .await?;
     println!("Stored to id {id}");
     Ok(())
 }
@@ -1,16 +1,20 @@
 use embedded_io_async::{ErrorType, Read, Write};
 
 use crate::{
-    constants::{AutoEnrollStep, AutoIdentCount, AutoIdentifyStep, Commands, ConfirmationCode, IdentifySafety, PackageIdentifier}, wire_traits::{FromWire, ToWire}, Checksum, Command, Error, Response
+    constants::{AutoEnrollStep, AutoIdentCount, AutoIdentifyStep, Commands, IdentifySafety, ModelId}, timeout::{resync_on_timeout, with_timeout, DelaySource, NoTimeout, AUTO_STEP_TIMEOUT_MS}, wire_traits::{FromWire, ToWire}, frame::FrameError, Command, Error, Response, R503
 };
 
 //////////////////////////////////////////////////////////////////////////////
 // Auto Enroll
 //////////////////////////////////////////////////////////////////////////////
 
-pub struct AutoEnroll<'a, S: Read + Write + ErrorType> {
+pub struct AutoEnroll<'a, S: Read + Write + ErrorType, D: DelaySource = NoTimeout> {
     address: u32,
     serial: &'a mut S,
+    delay: D,
+    skipped: usize,
+    /// From the config passed to [`Self::start()`]
+    pub(crate) return_status: bool,
 }
 
 pub struct AutoEnrollLocation {
@@ -18,11 +22,13 @@ pub struct AutoEnrollLocation {
 }
 
 impl AutoEnrollLocation {
-    pub fn specific(loc: u8) -> Option<Self> {
-        if (0x00..0xC8).contains(&loc) {
-            Some(Self { val: loc })
-        } else {
-            None
+    /// Store at `id`. The location is a single byte on the wire, with 0xC8
+    /// meaning automatic, so only the first 200 slots can be picked here.
+    /// Use [`R503::enroll()`] for the rest of a larger library.
+    pub fn specific(id: ModelId) -> Option<Self> {
+        match u8::try_from(id.get()) {
+            Ok(val) if val < 0xC8 => Some(Self { val }),
+            _ => None,
         }
     }
 
@@ -61,11 +67,7 @@ impl ToWire for AutoEnrollConfig {
         5
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
+    fn to_wire(&self, out: &mut [u8]) {
         let data = [
             self.location.val,
             self.cover_id as u8,
@@ -73,35 +75,36 @@ impl ToWire for AutoEnrollConfig {
             self.return_status as u8,
             self.require_release as u8,
         ];
-        if let Some(c) = cksm {
-            c.update(&data);
-        }
-        serial.write_all(&data).await.map_err(Error::Wire)
+        out.copy_from_slice(&data);
     }
 }
 
+/// Progress of an enrollment, see [`AutoEnroll::next_event()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnrollEvent {
+    /// A step finished, with more to come
+    Step(AutoEnrollStep),
+    /// The template was stored at `model_id`, and the enrollment is done
+    Stored { model_id: ModelId },
+}
+
 #[derive(Debug, PartialEq)]
 pub struct AutoEnrollResponse {
-    pub step: AutoEnrollStep,
-    pub model_id: u8,
+    /// `None` for the single acknowledgement sent without `return_status`
+    pub step: Option<AutoEnrollStep>,
+    pub model_id: ModelId,
 }
 
 impl FromWire for AutoEnrollResponse {
-    async fn from_wire<S: Read + ErrorType>(
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<Self, Error<S>> {
-        let bytes = <[u8; 3]>::from_wire(serial, cksm).await?;
-
-        // I'm not sure what this unused byte is for?
-        // is the ID actually a BE u16?
-        let [step, _unused, id] = bytes;
-
-        let Ok(step) = AutoEnrollStep::try_from(step) else {
-            return Err(Error::IncorrectData);
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let step = match u8::from_wire(buf)? {
+            0x00 => None,
+            step => Some(AutoEnrollStep::try_from(step).map_err(|_| FrameError::UnknownValue {
+                kind: "AutoEnrollStep",
+                value: step.into(),
+            })?),
         };
-        // TODO: it seems like model id is returned in every step?
-        let model_id = id;
+        let model_id = ModelId::from_wire(buf)?;
 
         Ok(Self { step, model_id })
     }
@@ -112,32 +115,54 @@ where
     S: Read + Write + ErrorType,
 {
     pub fn new(address: u32, serial: &'a mut S) -> Self {
-        Self { address, serial }
+        Self {
+            address,
+            serial,
+            delay: NoTimeout,
+            skipped: 0,
+            return_status: true,
+        }
+    }
+}
+
+impl<'a, S, D> AutoEnroll<'a, S, D>
+where
+    S: Read + Write + ErrorType,
+    D: DelaySource,
+{
+    /// Give up on each step after [`AUTO_STEP_TIMEOUT_MS`]
+    pub fn with_delay<D2: DelaySource>(self, delay: D2) -> AutoEnroll<'a, S, D2> {
+        AutoEnroll {
+            address: self.address,
+            serial: self.serial,
+            delay,
+            skipped: self.skipped,
+            return_status: self.return_status,
+        }
+    }
+
+    /// See [`R503::take_skipped_bytes()`]
+    pub fn take_skipped_bytes(&mut self) -> usize {
+        core::mem::take(&mut self.skipped)
     }
 
     /// All the steps, without yielding back control to get progress
     /// notifications
-    pub async fn oneshot(mut self, cfg: AutoEnrollConfig) -> Result<u8, Error<S>> {
-        self.start(cfg).await?;
-        self.wait_collect_image1().await?;
-        self.wait_generate_feature1().await?;
-        self.wait_collect_image2().await?;
-        self.wait_generate_feature2().await?;
-        self.wait_collect_image3().await?;
-        self.wait_generate_feature3().await?;
-        self.wait_collect_image4().await?;
-        self.wait_generate_feature4().await?;
-        self.wait_collect_image5().await?;
-        self.wait_generate_feature5().await?;
-        self.wait_collect_image6().await?;
-        self.wait_generate_feature6().await?;
-        self.wait_repeatfingerprint().await?;
-        self.wait_merge_feature().await?;
-        self.wait_storage_template().await
+    pub async fn oneshot(mut self, cfg: AutoEnrollConfig) -> Result<ModelId, Error<S>> {
+        self.run(cfg, |_| {}).await
+    }
+
+    /// Abort the enrollment, see [`R503::cancel()`]
+    pub async fn cancel(self) -> Result<(), Error<S>> {
+        R503::new_with_address(self.address)
+            .with_delay(self.delay)
+            .cancel(self.serial)
+            .await
     }
 
     /// Step 0
     pub async fn start(&mut self, cfg: AutoEnrollConfig) -> Result<(), Error<S>> {
+        self.return_status = cfg.return_status;
         let command = Command {
             address: self.address,
             instruction: Commands::AutomaticRegistrationTemplate,
@@ -146,111 +171,47 @@ where
         command.to_wire(self.serial).await
     }
 
-    // 0x01: Collect image for the first time
-    pub async fn wait_collect_image1(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage1)
-            .await
-            .map(drop)
-    }
-    // 0x02: Generate Feature for the first time
-    pub async fn wait_generate_feature1(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature1)
-            .await
-            .map(drop)
-    }
-    // 0x03: Collect image for the second time
-    pub async fn wait_collect_image2(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage2)
-            .await
-            .map(drop)
-    }
-    // 0x04: Generate Feature for the second time
-    pub async fn wait_generate_feature2(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature2)
-            .await
-            .map(drop)
-    }
-    // 0x05: Collect image for the third time
-    pub async fn wait_collect_image3(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage3)
-            .await
-            .map(drop)
-    }
-    // 0x06: Generate Feature for the third time
-    pub async fn wait_generate_feature3(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature3)
-            .await
-            .map(drop)
-    }
-    // 0x07: Collect image for the fourth time
-    pub async fn wait_collect_image4(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage4)
-            .await
-            .map(drop)
-    }
-    // 0x08: Generate Feature for the fourth time
-    pub async fn wait_generate_feature4(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature4)
-            .await
-            .map(drop)
-    }
-    // 0x09: Collect image for the fifth time
-    pub async fn wait_collect_image5(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage5)
-            .await
-            .map(drop)
-    }
-    // 0x10: Generate Feature for the fifth time
-    pub async fn wait_generate_feature5(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature5)
-            .await
-            .map(drop)
-    }
-    // 0x11: Collect image for the sixth time
-    pub async fn wait_collect_image6(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::CollectImage6)
-            .await
-            .map(drop)
-    }
-    // 0x12: Generate Feature for the sixth time
-    pub async fn wait_generate_feature6(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::GenerateFeature6)
-            .await
-            .map(drop)
-    }
-    // 0x0D: Repeat fingerprint check
-    pub async fn wait_repeatfingerprint(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::Repeatfingerprint)
-            .await
-            .map(drop)
-    }
-    // 0x0E: Merge feature
-    pub async fn wait_merge_feature(&mut self) -> Result<(), Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::MergeFeature)
-            .await
-            .map(drop)
-    }
-    // 0x0F: Storage template
-    pub async fn wait_storage_template(&mut self) -> Result<u8, Error<S>> {
-        self.wait_step(self.address, AutoEnrollStep::StorageTemplate)
-            .await
+    /// Wait for the next step to finish.
+    ///
+    /// Steps are reported in whatever order they arrive, firmware may skip
+    /// some. [`EnrollEvent::Stored`] is always the last event. Without
+    /// `return_status` in the config, the module only sends that one.
+    pub async fn next_event(&mut self) -> Result<EnrollEvent, Error<S>> {
+        let res = with_timeout(
+            &self.delay,
+            AUTO_STEP_TIMEOUT_MS,
+            Response::<AutoEnrollResponse>::from_wire(self.serial),
+        )
+        .await;
+        let resp = resync_on_timeout(&self.delay, self.serial, res).await?;
+        self.skipped += resp.skipped();
+        let AutoEnrollResponse { step, model_id } = resp.into_ack_body(self.address)?;
+
+        match step {
+            _ if !self.return_status => Ok(EnrollEvent::Stored { model_id }),
+            Some(AutoEnrollStep::StorageTemplate) => Ok(EnrollEvent::Stored { model_id }),
+            Some(step) => Ok(EnrollEvent::Step(step)),
+            None => Err(Error::UnknownValue {
+                kind: "AutoEnrollStep",
+                value: 0,
+            }),
+        }
     }
 
-    async fn wait_step(&mut self, address: u32, step: AutoEnrollStep) -> Result<u8, Error<S>> {
-        let resp = Response::<AutoEnrollResponse>::from_wire(self.serial).await?;
-        let mut good = true;
-        good &= resp.address == address;
-        good &= resp.ident == PackageIdentifier::AcknowledgePacket.into();
-        if !good {
-            return Err(Error::IncorrectData);
-        }
-        if resp.confirmation != ConfirmationCode::SuccessCode {
-            return Err(Error::BadConfirmation(resp.confirmation));
-        }
-        if resp.body.step != step {
-            return Err(Error::IncorrectData);
+    /// Start enrolling, and report each step to `on_event` until the
+    /// template is stored. Returns the model id it was stored at.
+    pub async fn run<F>(&mut self, cfg: AutoEnrollConfig, mut on_event: F) -> Result<ModelId, Error<S>>
+    where
+        F: FnMut(EnrollEvent),
+    {
+        self.start(cfg).await?;
+        loop {
+            let event = self.next_event().await?;
+            on_event(event);
+            if let EnrollEvent::Stored { model_id } = event {
+                return Ok(model_id);
+            }
         }
-        Ok(resp.body.model_id)
     }
 }
 
@@ -258,16 +219,42 @@ where
 // Auto Identify
 //////////////////////////////////////////////////////////////////////////////
 
-pub struct AutoIdentify<'a, S: Read + Write + ErrorType> {
+pub struct AutoIdentify<'a, S: Read + Write + ErrorType, D: DelaySource = NoTimeout> {
     address: u32,
     serial: &'a mut S,
+    delay: D,
+    skipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoIdentifyRange {
+    start: u8,
+    end: u8,
+}
+
+impl AutoIdentifyRange {
+    /// Search from `start` through `end`. Both are a single byte on the wire,
+    /// so AutoIdentify only reaches the first 256 slots. Use
+    /// [`R503::identify()`] for the rest of a larger library.
+    pub fn new(start: ModelId, end: ModelId) -> Option<Self> {
+        let start = u8::try_from(start.get()).ok()?;
+        let end = u8::try_from(end.get()).ok()?;
+        (start <= end).then_some(Self { start, end })
+    }
+}
+
+impl Default for AutoIdentifyRange {
+    /// Slots 0 through 199, a whole R503 library
+    fn default() -> Self {
+        Self { start: 0, end: 199 }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AutoIdentifyConfig {
     pub grade: IdentifySafety,
-    pub start_pos: u8,
-    pub steps_or_end: u8,
+    /// Slots to search
+    pub range: AutoIdentifyRange,
     pub return_status: bool,
     pub err_count: AutoIdentCount,
 }
@@ -282,8 +269,7 @@ impl Default for AutoIdentifyConfig {
     fn default() -> Self {
         Self {
             grade: IdentifySafety::Three,
-            start_pos: 0,
-            steps_or_end: 199,
+            range: AutoIdentifyRange::default(),
             return_status: true,
             err_count: AutoIdentCount::TimesWithTimeout(0xFF),
         }
@@ -295,49 +281,36 @@ impl ToWire for AutoIdentifyConfig {
         5
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
+    fn to_wire(&self, out: &mut [u8]) {
         let data = [
             self.grade.into(),
-            self.start_pos,
-            self.steps_or_end,
+            self.range.start,
+            self.range.end,
             self.return_status as u8,
             self.err_count.into(),
         ];
-        if let Some(c) = cksm {
-            c.update(&data);
-        }
-        serial.write_all(&data).await.map_err(Error::Wire)
+        out.copy_from_slice(&data);
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct AutoIdentifyResponse {
     pub step: AutoIdentifyStep,
-    pub model_id: u8,
+    pub model_id: ModelId,
     pub score: u16,
 }
 
 impl FromWire for AutoIdentifyResponse {
-    async fn from_wire<S: Read + ErrorType>(
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<Self, Error<S>> {
-        let bytes = <[u8; 5]>::from_wire(serial, cksm).await?;
-
-        // I'm not sure what this unused byte is for?
-        // is the ID actually a BE u16?
-        let [step, _unused, id, score_hi, score_lo] = bytes;
-
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let step = u8::from_wire(buf)?;
         let Ok(step) = AutoIdentifyStep::try_from(step) else {
-            return Err(Error::IncorrectData);
+            return Err(FrameError::UnknownValue {
+                kind: "AutoIdentifyStep",
+                value: step.into(),
+            });
         };
-        // TODO: it seems like model id is returned in every step?
-        let model_id = id;
-        let score = u16::from_be_bytes([score_hi, score_lo]);
+        let model_id = ModelId::from_wire(buf)?;
+        let score = u16::from_wire(buf)?;
 
         Ok(Self { step, model_id, score })
     }
@@ -348,7 +321,41 @@ where
     S: Read + Write + ErrorType,
 {
     pub fn new(address: u32, serial: &'a mut S) -> Self {
-        Self { address, serial }
+        Self {
+            address,
+            serial,
+            delay: NoTimeout,
+            skipped: 0,
+        }
+    }
+}
+
+impl<'a, S, D> AutoIdentify<'a, S, D>
+where
+    S: Read + Write + ErrorType,
+    D: DelaySource,
+{
+    /// Give up on each step after [`AUTO_STEP_TIMEOUT_MS`]
+    pub fn with_delay<D2: DelaySource>(self, delay: D2) -> AutoIdentify<'a, S, D2> {
+        AutoIdentify {
+            address: self.address,
+            serial: self.serial,
+            delay,
+            skipped: self.skipped,
+        }
+    }
+
+    /// See [`R503::take_skipped_bytes()`]
+    pub fn take_skipped_bytes(&mut self) -> usize {
+        core::mem::take(&mut self.skipped)
+    }
+
+    /// Abort the identification, see [`R503::cancel()`]
+    pub async fn cancel(self) -> Result<(), Error<S>> {
+        R503::new_with_address(self.address)
+            .with_delay(self.delay)
+            .cancel(self.serial)
+            .await
     }
 
     /// Step 0
@@ -361,53 +368,43 @@ where
         command.to_wire(self.serial).await
     }
 
+    /// Wait for the search result.
+    ///
+    /// Firmware sometimes skips straight to the result when it recognizes the
+    /// finger, so it is accepted after any step.
     pub async fn wait_auto(&mut self) -> Result<AutoIdentifyResponse, Error<S>> {
-        if let Some(r) = self.wait_collect_image().await? {
-            return Ok(r);
+        for step in [AutoIdentifyStep::CollectImage, AutoIdentifyStep::GenerateFeature] {
+            let resp = self.next_response().await?;
+            match resp.step {
+                AutoIdentifyStep::Search => return Ok(resp),
+                actual if actual == step => {}
+                actual => {
+                    return Err(Error::WrongStep {
+                        expected: step.into(),
+                        actual: actual.into(),
+                    })
+                }
+            }
         }
-        if let Some(r) = self.wait_generate_feature().await? {
-            return Ok(r);
+        let resp = self.next_response().await?;
+        match resp.step {
+            AutoIdentifyStep::Search => Ok(resp),
+            actual => Err(Error::WrongStep {
+                expected: AutoIdentifyStep::Search.into(),
+                actual: actual.into(),
+            }),
         }
-        self.wait_search().await
     }
 
-    // Originally these steps were public, but it seems like SOMETIMES the device just decides
-    // to skip straight to the end if it finds a finger it knows. For that reason, the
-    // `wait_auto` function catches this now, and users should just use that instead.
-
-    async fn wait_collect_image(&mut self) -> Result<Option<AutoIdentifyResponse>, Error<S>> {
-        self.wait_step(self.address, AutoIdentifyStep::CollectImage).await
-    }
-
-    async fn wait_generate_feature(&mut self) -> Result<Option<AutoIdentifyResponse>, Error<S>> {
-        self.wait_step(self.address, AutoIdentifyStep::GenerateFeature).await
-    }
-
-    async fn wait_search(&mut self) -> Result<AutoIdentifyResponse, Error<S>> {
-        if let Some(r) = self.wait_step(self.address, AutoIdentifyStep::Search).await? {
-            Ok(r)
-        } else {
-            Err(Error::IncorrectData)
-        }
-    }
-
-    async fn wait_step(&mut self, address: u32, step: AutoIdentifyStep) -> Result<Option<AutoIdentifyResponse>, Error<S>> {
-        let resp = Response::<AutoIdentifyResponse>::from_wire(self.serial).await?;
-        let mut good = true;
-        good &= resp.address == address;
-        good &= resp.ident == PackageIdentifier::AcknowledgePacket.into();
-        if !good {
-            return Err(Error::IncorrectData);
-        }
-        if resp.confirmation != ConfirmationCode::SuccessCode {
-            return Err(Error::BadConfirmation(resp.confirmation));
-        }
-        if resp.body.step == AutoIdentifyStep::Search {
-            Ok(Some(resp.body))
-        } else if resp.body.step != step {
-            Err(Error::IncorrectData)
-        } else {
-            Ok(None)
-        }
+    async fn next_response(&mut self) -> Result<AutoIdentifyResponse, Error<S>> {
+        let res = with_timeout(
+            &self.delay,
+            AUTO_STEP_TIMEOUT_MS,
+            Response::<AutoIdentifyResponse>::from_wire(self.serial),
+        )
+        .await;
+        let resp = resync_on_timeout(&self.delay, self.serial, res).await?;
+        self.skipped += resp.skipped();
+        resp.into_ack_body(self.address)
     }
 }
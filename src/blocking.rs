@@ -0,0 +1,353 @@
+//! Blocking versions of [`crate::R503`], [`crate::auto::AutoEnroll`] and
+//! [`crate::auto::AutoIdentify`], over `embedded_io::{Read, Write}`.
+//!
+//! These run the async implementation to completion on the spot, through the
+//! [`Blocking`] adapter, so both share the same protocol code. There are no
+//! driver timeouts here: a read that never returns blocks forever, so use the
+//! port's own read timeout if it has one.
+
+use core::ops::RangeInclusive;
+
+use embassy_futures::block_on;
+use embedded_io::{ErrorType, Read, Write};
+
+use crate::{
+    auto::{AutoEnrollConfig, AutoIdentifyConfig, AutoIdentifyResponse, EnrollEvent},
+    enroll::{EnrollConfig, EnrollError, EnrollProgress},
+    index::TemplateIndex,
+    constants::{AuraControlPayload, BaudRate, CharBufferId, IndexTableIdx, ModelId, PacketLength, Password, SystemParameter},
+    notepad::{NotepadPage, WriteNotepadRequest, NOTEPAD_PAGE_SIZE},
+    verify::MatchResult,
+    DeleteCharRequest, Error, LoadCharRequest, MatchResponse, ProductInfo, SearchRequest, SearchResponse, StoreRequest,
+    SystemParameters, VersionString,
+};
+
+/// Implements the async `embedded_io_async` traits over a blocking port.
+///
+/// Every operation completes before its future is first polled to an end, so
+/// the futures never return `Pending`.
+pub struct Blocking<T>(T);
+
+impl<T> Blocking<T> {
+    pub fn new(inner: T) -> Self {
+        Self(inner)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: ErrorType> ErrorType for Blocking<T> {
+    type Error = T::Error;
+}
+
+impl<T: Read> embedded_io_async::Read for Blocking<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: Write> embedded_io_async::Write for Blocking<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
+
+/// Blocking counterpart of [`crate::BaudRateControl`]
+pub trait BaudRateControl: ErrorType {
+    /// Retune the host side of the UART to `baud_rate`
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<(), Self::Error>;
+}
+
+impl<T: BaudRateControl + ?Sized> BaudRateControl for &mut T {
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<(), Self::Error> {
+        T::set_baud_rate(self, baud_rate)
+    }
+}
+
+impl<T: BaudRateControl> crate::BaudRateControl for Blocking<T> {
+    async fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<(), Self::Error> {
+        self.0.set_baud_rate(baud_rate)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// R503
+//////////////////////////////////////////////////////////////////////////////
+
+/// Blocking counterpart of [`crate::R503`]
+pub struct R503 {
+    inner: crate::R503,
+}
+
+// Blocking wrappers for the command table in `lib.rs`
+macro_rules! blocking_cmds {
+    (
+        | Function      | Code          | Timeout           | Retry         | CmdDataTy     | RespDataTy    |
+        | $(-)*         | $(-)*         | $(-)*             | $(-)*         | $(-)*         | $(-)*         |
+     $( | $func:ident   | $code:ident   | $timeout:literal  | $retry:ident  | $($cdt:ty)?   | $($rdy:ty)?   | )*
+    ) => {
+        $(
+            #[allow(unused_parens)]
+            pub fn $func<S>(&self, serial: &mut S, $(arg: $cdt)?) -> Result<($($rdy)?), Error<S>>
+            where
+                S: Read + Write,
+            {
+                block_on(self.inner.$func(&mut Blocking(serial), $({
+                    let arg: $cdt = arg;
+                    arg
+                })?))
+                .map_err(Error::cast)
+            }
+        )*
+    };
+}
+
+impl R503 {
+    pub fn new_with_address(addr: u32) -> Self {
+        Self {
+            inner: crate::R503::new_with_address(addr),
+        }
+    }
+
+    pub fn address(&self) -> u32 {
+        self.inner.address()
+    }
+
+    /// See [`crate::R503::packet_length()`]
+    pub fn packet_length(&self) -> PacketLength {
+        self.inner.packet_length()
+    }
+
+    pub fn set_packet_length(&mut self, packet_length: PacketLength) {
+        self.inner.set_packet_length(packet_length);
+    }
+
+    /// See [`crate::R503::with_retry()`]
+    pub fn with_retry(self, retry: crate::retry::RetryPolicy) -> Self {
+        Self {
+            inner: self.inner.with_retry(retry),
+        }
+    }
+
+    /// See [`crate::R503::take_skipped_bytes()`]
+    pub fn take_skipped_bytes(&self) -> usize {
+        self.inner.take_skipped_bytes()
+    }
+
+    /// See [`crate::R503::download_template()`]
+    pub fn download_template<S: Read + Write>(
+        &self,
+        serial: &mut S,
+        char_buffer: CharBufferId,
+        template: &[u8],
+    ) -> Result<(), Error<S>> {
+        block_on(self.inner.download_template(&mut Blocking(serial), char_buffer, template)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::cancel()`]
+    pub fn cancel<S: Read + Write>(&self, serial: &mut S) -> Result<(), Error<S>> {
+        block_on(self.inner.cancel(&mut Blocking(serial))).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::read_info_page()`]
+    pub fn read_info_page<S: Read + Write>(&self, serial: &mut S, out_buf: &mut [u8]) -> Result<usize, Error<S>> {
+        block_on(self.inner.read_info_page(&mut Blocking(serial), out_buf)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::set_address()`]
+    pub fn set_address<S: Read + Write>(self, serial: &mut S, address: u32) -> Result<Self, (Self, Error<S>)> {
+        match block_on(self.inner.set_address(&mut Blocking(serial), address)) {
+            Ok(inner) => Ok(Self { inner }),
+            Err((inner, e)) => Err((Self { inner }, e.cast())),
+        }
+    }
+
+    /// See [`crate::R503::download_image()`]
+    pub fn download_image<S: Read + Write>(&self, serial: &mut S, image: &[u8]) -> Result<(), Error<S>> {
+        block_on(self.inner.download_image(&mut Blocking(serial), image)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::stream_image()`]
+    pub fn stream_image<S: Read>(&self, serial: &mut S, out_buf: &mut [u8]) -> Result<usize, Error<S>> {
+        block_on(self.inner.stream_image(&mut Blocking(serial), out_buf)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::reconfigure_baud_rate()`]. A module that is silent
+    /// at the new rate is only noticed if `serial` has a read timeout.
+    pub fn reconfigure_baud_rate<S>(&self, serial: &mut S, baud_rate: BaudRate) -> Result<(), Error<S>>
+    where
+        S: Read + Write + BaudRateControl,
+    {
+        block_on(self.inner.migrate_baud_rate(&mut Blocking(serial), baud_rate)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::reconfigure_packet_length()`]
+    pub fn reconfigure_packet_length<S: Read + Write>(
+        &mut self,
+        serial: &mut S,
+        packet_length: PacketLength,
+    ) -> Result<PacketLength, Error<S>> {
+        block_on(self.inner.reconfigure_packet_length(&mut Blocking(serial), packet_length)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::sync_packet_length()`]
+    pub fn sync_packet_length<S: Read + Write>(&mut self, serial: &mut S) -> Result<PacketLength, Error<S>> {
+        block_on(self.inner.sync_packet_length(&mut Blocking(serial))).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::enroll()`]
+    pub fn enroll<S, F>(&self, serial: &mut S, cfg: EnrollConfig, on_progress: F) -> Result<(), EnrollError<S>>
+    where
+        S: Read + Write,
+        F: FnMut(EnrollProgress),
+    {
+        block_on(self.inner.enroll(&mut Blocking(serial), cfg, on_progress)).map_err(EnrollError::cast)
+    }
+
+    /// See [`crate::R503::read_template_index()`]
+    pub fn read_template_index<S: Read + Write>(&self, serial: &mut S) -> Result<TemplateIndex, Error<S>> {
+        block_on(self.inner.read_template_index(&mut Blocking(serial))).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::verify()`]
+    pub fn verify<S: Read + Write>(&self, serial: &mut S, model_id: ModelId) -> Result<Option<MatchResult>, Error<S>> {
+        block_on(self.inner.verify(&mut Blocking(serial), model_id)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::identify()`]
+    pub fn identify<S: Read + Write>(
+        &self,
+        serial: &mut S,
+        range: RangeInclusive<ModelId>,
+    ) -> Result<Option<MatchResult>, Error<S>> {
+        block_on(self.inner.identify(&mut Blocking(serial), range)).map_err(Error::cast)
+    }
+
+    /// See [`crate::R503::wait_ready()`]
+    pub fn wait_ready<S: Read>(&self, serial: &mut S) -> Result<(), Error<S>> {
+        block_on(self.inner.wait_ready(&mut Blocking(serial))).map_err(Error::cast)
+    }
+
+    crate::command_table!(blocking_cmds);
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Auto Enroll / Auto Identify
+//////////////////////////////////////////////////////////////////////////////
+
+/// Blocking counterpart of [`crate::auto::AutoEnroll`]
+pub struct AutoEnroll<'a, S: Read + Write> {
+    address: u32,
+    serial: Blocking<&'a mut S>,
+    skipped: usize,
+    return_status: bool,
+}
+
+impl<'a, S: Read + Write> AutoEnroll<'a, S> {
+    pub fn new(address: u32, serial: &'a mut S) -> Self {
+        Self {
+            address,
+            serial: Blocking(serial),
+            skipped: 0,
+            return_status: true,
+        }
+    }
+
+    /// A fresh async AutoEnroll for one call. It holds no state besides the
+    /// config flag and the skipped byte count.
+    fn inner(&mut self) -> crate::auto::AutoEnroll<'_, Blocking<&'a mut S>> {
+        let mut inner = crate::auto::AutoEnroll::new(self.address, &mut self.serial);
+        inner.return_status = self.return_status;
+        inner
+    }
+
+    /// See [`crate::auto::AutoEnroll::oneshot()`]
+    pub fn oneshot(mut self, cfg: AutoEnrollConfig) -> Result<ModelId, Error<S>> {
+        self.run(cfg, |_| {})
+    }
+
+    /// See [`crate::auto::AutoEnroll::cancel()`]
+    pub fn cancel(self) -> Result<(), Error<S>> {
+        let mut serial = self.serial;
+        block_on(crate::auto::AutoEnroll::new(self.address, &mut serial).cancel()).map_err(Error::cast)
+    }
+
+    /// See [`crate::auto::AutoEnroll::start()`]
+    pub fn start(&mut self, cfg: AutoEnrollConfig) -> Result<(), Error<S>> {
+        self.return_status = cfg.return_status;
+        block_on(self.inner().start(cfg)).map_err(Error::cast)
+    }
+
+    /// See [`crate::auto::AutoEnroll::next_event()`]
+    pub fn next_event(&mut self) -> Result<EnrollEvent, Error<S>> {
+        let mut inner = self.inner();
+        let res = block_on(inner.next_event());
+        let skipped = inner.take_skipped_bytes();
+        self.skipped += skipped;
+        res.map_err(Error::cast)
+    }
+
+    /// See [`crate::auto::AutoEnroll::run()`]
+    pub fn run<F>(&mut self, cfg: AutoEnrollConfig, on_event: F) -> Result<ModelId, Error<S>>
+    where
+        F: FnMut(EnrollEvent),
+    {
+        let mut inner = self.inner();
+        let res = block_on(inner.run(cfg, on_event));
+        let return_status = inner.return_status;
+        let skipped = inner.take_skipped_bytes();
+        self.return_status = return_status;
+        self.skipped += skipped;
+        res.map_err(Error::cast)
+    }
+
+    pub fn take_skipped_bytes(&mut self) -> usize {
+        core::mem::take(&mut self.skipped)
+    }
+}
+
+/// Blocking counterpart of [`crate::auto::AutoIdentify`]
+pub struct AutoIdentify<'a, S: Read + Write> {
+    address: u32,
+    serial: Blocking<&'a mut S>,
+    skipped: usize,
+}
+
+impl<'a, S: Read + Write> AutoIdentify<'a, S> {
+    pub fn new(address: u32, serial: &'a mut S) -> Self {
+        Self {
+            address,
+            serial: Blocking(serial),
+            skipped: 0,
+        }
+    }
+
+    /// See [`crate::auto::AutoIdentify::cancel()`]
+    pub fn cancel(self) -> Result<(), Error<S>> {
+        let mut serial = self.serial;
+        block_on(crate::auto::AutoIdentify::new(self.address, &mut serial).cancel()).map_err(Error::cast)
+    }
+
+    /// See [`crate::auto::AutoIdentify::start()`]
+    pub fn start(&mut self, cfg: AutoIdentifyConfig) -> Result<(), Error<S>> {
+        block_on(crate::auto::AutoIdentify::new(self.address, &mut self.serial).start(cfg)).map_err(Error::cast)
+    }
+
+    /// See [`crate::auto::AutoIdentify::wait_auto()`]
+    pub fn wait_auto(&mut self) -> Result<AutoIdentifyResponse, Error<S>> {
+        let mut inner = crate::auto::AutoIdentify::new(self.address, &mut self.serial);
+        let res = block_on(inner.wait_auto());
+        self.skipped += inner.take_skipped_bytes();
+        res.map_err(Error::cast)
+    }
+
+    pub fn take_skipped_bytes(&mut self) -> usize {
+        core::mem::take(&mut self.skipped)
+    }
+}
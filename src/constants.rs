@@ -1,4 +1,17 @@
-use crate::{wire_traits::ToWire, Error};
+use crate::{
+    frame::FrameError,
+    wire_traits::{put, FromWire, ToWire},
+};
+
+/// Width of the sensor image, in pixels
+pub const IMAGE_WIDTH: usize = 192;
+/// Height of the sensor image, in pixels
+pub const IMAGE_HEIGHT: usize = 192;
+/// Size of an image on the wire, in bytes.
+///
+/// Only the high four bits of each pixel are transferred, two horizontally
+/// adjacent pixels per byte (left pixel in the high nibble).
+pub const PACKED_IMAGE_SIZE: usize = IMAGE_WIDTH * IMAGE_HEIGHT / 2;
 
 // Helper macro that generate a lot of accessors for enum to integer conversions
 macro_rules! be_enum {
@@ -45,25 +58,21 @@ macro_rules! be_enum {
                 size_of::<$int_ty>()
             }
 
-            async fn to_wire<S: embedded_io_async::Write + embedded_io_async::ErrorType>(
-                &self,
-                serial: &mut S,
-                cksm: Option<&mut crate::Checksum>,
-            ) -> Result<(), crate::Error<S>> {
+            fn to_wire(&self, out: &mut [u8]) {
                 let val: $int_ty = (*self).into();
-                val.to_wire(serial, cksm).await
+                val.to_wire(out)
             }
         }
 
         impl crate::FromWire for $enum_name {
-            async fn from_wire<S: embedded_io_async::Read + embedded_io_async::ErrorType>(
-                serial: &mut S,
-                cksm: Option<&mut crate::Checksum>,
-            ) -> Result<Self, crate::Error<S>> {
-                let val = <$int_ty>::from_wire(serial, cksm).await?;
+            fn from_wire(buf: &mut &[u8]) -> Result<Self, crate::frame::FrameError> {
+                let val = <$int_ty>::from_wire(buf)?;
                 match Self::try_from(val) {
                     Ok(v) => Ok(v),
-                    Err(_) => Err(crate::Error::IncorrectData)
+                    Err(_) => Err(crate::frame::FrameError::UnknownValue {
+                        kind: stringify!($enum_name),
+                        value: val.into(),
+                    }),
                 }
             }
         }
@@ -89,17 +98,39 @@ be_enum! {
     {
         GetImage -> 0x01,
         GenChar -> 0x02,
+        Match -> 0x03,
+        Search -> 0x04,
         RegModel -> 0x05,
+        Store -> 0x06,
         LoadChar -> 0x07,
         UpChar -> 0x08,
+        DownChar -> 0x09,
         UpImage -> 0x0A,
+        DownImage -> 0x0B,
+        DeleteChar -> 0x0C,
         Empty -> 0x0D,
         GetRandomCode -> 0x14,
+        SetAdder -> 0x15,
+        ReadInfPage -> 0x16,
+        WriteNotepad -> 0x18,
+        ReadNotepad -> 0x19,
+        SetSysPara -> 0x0E,
         ReadSystemParameter -> 0x0F,
+        SetPwd -> 0x12,
+        VfyPwd -> 0x13,
+        TempleteNum -> 0x1D,
         ReadIndexTable -> 0x1F,
+        GetImageEx -> 0x28,
+        Cancel -> 0x30,
         AutomaticRegistrationTemplate -> 0x31,
         AutomaticFingerprintVerification -> 0x32,
         AuraControl -> 0x35,
+        CheckSensor -> 0x36,
+        GetAlgVer -> 0x39,
+        GetFwVer -> 0x3A,
+        ReadProdInfo -> 0x3C,
+        SoftRst -> 0x3D,
+        HandShake -> 0x40,
     }
 }
 
@@ -256,6 +287,199 @@ be_enum! {
     }
 }
 
+/// A template slot in the fingerprint library.
+///
+/// Slots run from 0 up to the library size in the system parameters, 200 on
+/// the R503 and more on the R503-Pro, see
+/// [`crate::SystemParameters::model_id()`]. Ids in replies are taken as the
+/// module sends them. The default is slot 0, which every library has.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModelId(u16);
+
+impl ModelId {
+    /// `id`, if it is below `library_size`
+    pub fn new(id: u16, library_size: u16) -> Option<Self> {
+        (id < library_size).then_some(Self(id))
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// An id the module reported, or one already known to fit
+    pub(crate) fn from_raw(id: u16) -> Self {
+        Self(id)
+    }
+}
+
+impl From<ModelId> for u16 {
+    fn from(value: ModelId) -> Self {
+        value.0
+    }
+}
+
+impl core::fmt::Display for ModelId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ToWire for ModelId {
+    fn size_on_wire(&self) -> usize {
+        2
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        self.0.to_wire(out)
+    }
+}
+
+impl FromWire for ModelId {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        Ok(Self(u16::from_wire(buf)?))
+    }
+}
+
+/// Sent by the module once it has initialized after power on
+pub const READY: u8 = 0x55;
+
+/// Module handshaking password.
+///
+/// The default password is `0x00000000`. Once changed, the first command
+/// after power on must be VfyPwd, see [`crate::LockedR503`].
+pub type Password = u32;
+
+/// The factory default password
+pub const DEFAULT_PASSWORD: Password = 0x00000000;
+
+// Maximum data packet length, as configured in the module's system parameters
+be_enum! {
+    name: PacketLength;
+    integer: u8;
+    {
+        Bytes32 -> 0x00,
+        Bytes64 -> 0x01,
+        Bytes128 -> 0x02,
+        Bytes256 -> 0x03,
+    }
+}
+
+impl PacketLength {
+    /// Number of payload bytes carried by each data packet
+    pub fn bytes(&self) -> usize {
+        match self {
+            PacketLength::Bytes32 => 32,
+            PacketLength::Bytes64 => 64,
+            PacketLength::Bytes128 => 128,
+            PacketLength::Bytes256 => 256,
+        }
+    }
+}
+
+// Baud rate, as 9600 * N bps
+be_enum! {
+    name: BaudRate;
+    integer: u8;
+    {
+        Rate9600 -> 1,
+        Rate19200 -> 2,
+        Rate38400 -> 4,
+        Rate57600 -> 6,
+        Rate115200 -> 12,
+    }
+}
+
+impl BaudRate {
+    pub fn bits_per_second(&self) -> u32 {
+        9600 * u32::from(u8::from(*self))
+    }
+}
+
+// Matching threshold. Level 1 has the highest FAR and lowest FRR, level 5
+// has the lowest FAR and highest FRR
+be_enum! {
+    name: SecurityLevel;
+    integer: u8;
+    {
+        Level1 -> 1,
+        Level2 -> 2,
+        Level3 -> 3,
+        Level4 -> 4,
+        Level5 -> 5,
+    }
+}
+
+/// A single system parameter, as written by SetSysPara.
+///
+/// The module only applies most new settings after it has been power cycled.
+#[derive(Debug, Clone, Copy)]
+pub enum SystemParameter {
+    /// Parameter number 4
+    BaudRate(BaudRate),
+    /// Parameter number 5
+    SecurityLevel(SecurityLevel),
+    /// Parameter number 6
+    PacketLength(PacketLength),
+}
+
+impl ToWire for SystemParameter {
+    fn size_on_wire(&self) -> usize {
+        2
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        let data: [u8; 2] = match self {
+            SystemParameter::BaudRate(b) => [4, (*b).into()],
+            SystemParameter::SecurityLevel(l) => [5, (*l).into()],
+            SystemParameter::PacketLength(p) => [6, (*p).into()],
+        };
+        out.copy_from_slice(&data);
+    }
+}
+
+/// Contents of the system status register
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StatusRegister(u16);
+
+impl StatusRegister {
+    /// System is executing commands
+    pub const BUSY: u16 = 1 << 0;
+    /// Found the matching finger
+    pub const PASS: u16 = 1 << 1;
+    /// Verified the device's handshaking password
+    pub const PWD: u16 = 1 << 2;
+    /// Image buffer contains a valid image
+    pub const IMG_BUF_STAT: u16 = 1 << 3;
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(&self, flags: u16) -> bool {
+        self.0 & flags == flags
+    }
+
+    pub fn busy(&self) -> bool {
+        self.contains(Self::BUSY)
+    }
+
+    pub fn pass(&self) -> bool {
+        self.contains(Self::PASS)
+    }
+
+    pub fn password_verified(&self) -> bool {
+        self.contains(Self::PWD)
+    }
+
+    pub fn image_buffer_valid(&self) -> bool {
+        self.contains(Self::IMG_BUF_STAT)
+    }
+}
+
 be_enum! {
     name: AuraControlCode;
     integer: u8;
@@ -296,19 +520,12 @@ impl ToWire for AuraCycleCount {
         1
     }
 
-    async fn to_wire<S: embedded_io_async::Write + embedded_io_async::ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut crate::Checksum>,
-    ) -> Result<(), Error<S>> {
+    fn to_wire(&self, out: &mut [u8]) {
         let value: u8 = match self {
             AuraCycleCount::Infinite => 0,
             AuraCycleCount::Times(non_zero) => *non_zero,
         };
-        if let Some(c) = cksm {
-            c.update(&[value]);
-        }
-        serial.write_all(&[value]).await.map_err(Error::Wire)
+        value.to_wire(out)
     }
 }
 
@@ -325,24 +542,11 @@ impl ToWire for AuraControlPayload {
         4
     }
 
-    async fn to_wire<S: embedded_io_async::Write + embedded_io_async::ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut crate::Checksum>,
-    ) -> Result<(), Error<S>> {
-        // this is awkward
-        if let Some(cksm) = cksm {
-            self.ctrl_code.to_wire(serial, Some(cksm)).await?;
-            self.speed.to_wire(serial, Some(cksm)).await?;
-            self.color.to_wire(serial, Some(cksm)).await?;
-            self.count.to_wire(serial, Some(cksm)).await?;
-        } else {
-            self.ctrl_code.to_wire(serial, None).await?;
-            self.speed.to_wire(serial, None).await?;
-            self.color.to_wire(serial, None).await?;
-            self.count.to_wire(serial, None).await?;
-        }
-        Ok(())
+    fn to_wire(&self, out: &mut [u8]) {
+        let out = put(out, &self.ctrl_code);
+        let out = put(out, &self.speed);
+        let out = put(out, &self.color);
+        put(out, &self.count);
     }
 }
 
@@ -369,19 +573,12 @@ impl ToWire for AutoIdentCount {
         1
     }
 
-    async fn to_wire<S: embedded_io_async::Write + embedded_io_async::ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut crate::Checksum>,
-    ) -> Result<(), Error<S>> {
+    fn to_wire(&self, out: &mut [u8]) {
         let value: u8 = match self {
             AutoIdentCount::Infinite => 0,
             AutoIdentCount::TimesWithTimeout(non_zero) => *non_zero,
         };
-        if let Some(c) = cksm {
-            c.update(&[value]);
-        }
-        serial.write_all(&[value]).await.map_err(Error::Wire)
+        value.to_wire(out)
     }
 }
 
@@ -0,0 +1,269 @@
+use core::fmt::{Debug, Display};
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::{
+    constants::{CharBufferId, ConfirmationCode, ModelId},
+    timeout::DelaySource,
+    Error, SearchRequest, StoreRequest, R503,
+};
+
+/// Default pause between GetImage polls while waiting for a finger
+pub const POLL_INTERVAL_MS: u32 = 100;
+
+/// Fewest feature files RegModel can build a template from
+pub const MIN_SAMPLES: u8 = 2;
+/// Most feature files RegModel can build a template from, one per CharBuffer
+pub const MAX_SAMPLES: u8 = 6;
+
+const BUFFERS: [CharBufferId; MAX_SAMPLES as usize] = [
+    CharBufferId::One,
+    CharBufferId::Two,
+    CharBufferId::Three,
+    CharBufferId::Four,
+    CharBufferId::Five,
+    CharBufferId::Six,
+];
+
+#[derive(Debug, Clone)]
+pub struct EnrollConfig {
+    /// Number of finger presses, from [`MIN_SAMPLES`] to [`MAX_SAMPLES`]. The
+    /// manual recommends at least four.
+    pub samples: u8,
+    /// Library slot to store the template in
+    pub model_id: ModelId,
+    /// Search the library with the first sample, and stop if the finger is
+    /// already enrolled
+    pub check_duplicate: bool,
+    /// Pause between GetImage polls while waiting for a finger to be placed
+    /// or lifted. Only applies with a delay source.
+    pub poll_interval_ms: u32,
+}
+
+impl EnrollConfig {
+    /// Enroll into `model_id`, with four samples and a duplicate check.
+    ///
+    /// There is no default slot: Store overwrites whatever template is
+    /// there, see [`R503::read_template_index()`] to find a free one.
+    pub fn new(model_id: ModelId) -> Self {
+        Self {
+            samples: 4,
+            model_id,
+            check_duplicate: true,
+            poll_interval_ms: POLL_INTERVAL_MS,
+        }
+    }
+}
+
+/// Progress of [`R503::enroll()`]. Samples count from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnrollProgress {
+    /// Waiting for a finger on the sensor
+    PlaceFinger { sample: u8 },
+    /// The image was turned into a feature file
+    SampleTaken { sample: u8 },
+    /// Capturing the image failed, or it was too poor to use. The same
+    /// sample is taken again.
+    SampleRejected { sample: u8, code: ConfirmationCode },
+    /// Waiting for the finger to lift before the next press
+    LiftFinger,
+    /// The feature files were merged into a template
+    TemplateGenerated,
+    /// The template was stored, and the enrollment is done
+    Stored { model_id: ModelId },
+}
+
+pub enum EnrollError<S: ErrorType> {
+    Device(Error<S>),
+    /// The sample count is outside [`MIN_SAMPLES`]..=[`MAX_SAMPLES`]
+    BadSampleCount { samples: u8 },
+    /// The finger is already enrolled at `model_id`
+    Duplicate { model_id: ModelId, score: u16 },
+}
+
+impl<S: ErrorType> From<Error<S>> for EnrollError<S> {
+    fn from(value: Error<S>) -> Self {
+        EnrollError::Device(value)
+    }
+}
+
+impl<S: ErrorType> EnrollError<S> {
+    /// The same error, for another port with the same error type
+    pub(crate) fn cast<S2: ErrorType<Error = S::Error>>(self) -> EnrollError<S2> {
+        match self {
+            EnrollError::Device(e) => EnrollError::Device(e.cast()),
+            EnrollError::BadSampleCount { samples } => EnrollError::BadSampleCount { samples },
+            EnrollError::Duplicate { model_id, score } => EnrollError::Duplicate { model_id, score },
+        }
+    }
+}
+
+impl<S> Debug for EnrollError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EnrollError::Device(e) => {
+                f.write_str("EnrollError::Device(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            EnrollError::BadSampleCount { samples } => f
+                .debug_struct("EnrollError::BadSampleCount")
+                .field("samples", samples)
+                .finish(),
+            EnrollError::Duplicate { model_id, score } => f
+                .debug_struct("EnrollError::Duplicate")
+                .field("model_id", model_id)
+                .field("score", score)
+                .finish(),
+        }
+    }
+}
+
+impl<S> Display for EnrollError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EnrollError::Device(e) => write!(f, "{e}"),
+            EnrollError::BadSampleCount { samples } => {
+                write!(f, "{samples} samples, expected {MIN_SAMPLES} to {MAX_SAMPLES}")
+            }
+            EnrollError::Duplicate { model_id, score } => {
+                write!(f, "finger already enrolled at {model_id} (score {score})")
+            }
+        }
+    }
+}
+
+impl<S> core::error::Error for EnrollError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+}
+
+impl<D: DelaySource> R503<D> {
+    /// Enroll a finger from the host, one press at a time.
+    ///
+    /// Unlike AutoEnroll, the number of presses is up to the caller, and each
+    /// step is reported to `on_progress` so a UI can prompt for it. Each
+    /// sample goes through GetImage and GenChar into its own CharBuffer, then
+    /// RegModel merges them and Store saves the template. Failed captures and
+    /// images too poor to use are rejected and taken again, and the finger
+    /// has to lift between presses.
+    ///
+    /// Waiting for a finger polls forever, drop the future to give up.
+    pub async fn enroll<S, F>(
+        &self,
+        serial: &mut S,
+        cfg: EnrollConfig,
+        mut on_progress: F,
+    ) -> Result<(), EnrollError<S>>
+    where
+        S: Read + Write + ErrorType,
+        F: FnMut(EnrollProgress),
+    {
+        if !(MIN_SAMPLES..=MAX_SAMPLES).contains(&cfg.samples) {
+            return Err(EnrollError::BadSampleCount { samples: cfg.samples });
+        }
+
+        let mut sample = 1;
+        while sample <= cfg.samples {
+            on_progress(EnrollProgress::PlaceFinger { sample });
+            let res = match self.wait_for_finger(serial, cfg.poll_interval_ms).await {
+                Ok(()) => self.generate_char(serial, BUFFERS[usize::from(sample - 1)]).await,
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(()) => {
+                    on_progress(EnrollProgress::SampleTaken { sample });
+                    if sample == 1 && cfg.check_duplicate {
+                        self.check_duplicate(serial).await?;
+                    }
+                    sample += 1;
+                }
+                Err(Error::BadConfirmation(code)) => {
+                    on_progress(EnrollProgress::SampleRejected { sample, code });
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if sample <= cfg.samples {
+                on_progress(EnrollProgress::LiftFinger);
+                self.wait_for_lift(serial, cfg.poll_interval_ms).await?;
+            }
+        }
+
+        self.generate_template(serial).await?;
+        on_progress(EnrollProgress::TemplateGenerated);
+        let req = StoreRequest {
+            char_buffer: CharBufferId::One,
+            model_id: cfg.model_id,
+        };
+        self.store(serial, req).await?;
+        on_progress(EnrollProgress::Stored { model_id: cfg.model_id });
+        Ok(())
+    }
+
+    /// Poll GetImage until it captures an image
+    pub(crate) async fn wait_for_finger<S>(&self, serial: &mut S, poll_interval_ms: u32) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        loop {
+            match self.get_image(serial).await {
+                Err(Error::BadConfirmation(ConfirmationCode::NoFingerOnSensor)) => {
+                    self.poll_pause(poll_interval_ms).await
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Poll GetImage until it finds no finger
+    async fn wait_for_lift<S>(&self, serial: &mut S, poll_interval_ms: u32) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        loop {
+            match self.get_image(serial).await {
+                Err(Error::BadConfirmation(ConfirmationCode::NoFingerOnSensor)) => return Ok(()),
+                Ok(()) => self.poll_pause(poll_interval_ms).await,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn poll_pause(&self, ms: u32) {
+        if self.delay.is_enabled() {
+            self.delay.clone().delay_ms(ms).await;
+        }
+    }
+
+    /// Search the whole library for the features in CharBuffer 1
+    async fn check_duplicate<S>(&self, serial: &mut S) -> Result<(), EnrollError<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let library_size = self.read_system_parameter(serial).await?.library_size;
+        let req = SearchRequest {
+            char_buffer: CharBufferId::One,
+            start_id: ModelId::default(),
+            count: library_size,
+        };
+        match self.search(serial, req).await {
+            Ok(found) => Err(EnrollError::Duplicate {
+                model_id: found.model_id,
+                score: found.score,
+            }),
+            Err(Error::BadConfirmation(ConfirmationCode::FailToFindMatchingFinger)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
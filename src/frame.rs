@@ -0,0 +1,243 @@
+//! Sans-IO packet framing.
+//!
+//! [`encode()`] writes a complete packet into a buffer, and [`Decoder`] picks
+//! complete, checksum-verified packets out of bytes fed to it in slices of
+//! any size. Neither does any I/O, so they work the same on DMA buffers, USB
+//! frames or captured logs. The async and blocking drivers are thin layers
+//! over them.
+
+use crate::{
+    constants::PackageIdentifier,
+    wire_traits::{put, ToWire},
+    Checksum, MAX_HEADER_SKIP,
+};
+
+/// Start of every packet
+pub const HEADER: u16 = 0xEF01;
+/// Header, address, package identifier, length and checksum
+pub const FRAME_OVERHEAD: usize = 11;
+/// Largest packet content: a data packet at [`crate::constants::PacketLength::Bytes256`]
+pub const MAX_CONTENT_LEN: usize = 256;
+/// Largest complete packet
+pub const MAX_FRAME_LEN: usize = FRAME_OVERHEAD + MAX_CONTENT_LEN;
+
+/// Header, address, identifier and length, everything before the content
+const PREFIX_LEN: usize = 9;
+
+/// Problems with the framing or the contents of a packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameError {
+    BadChecksum { calculated: u16, reported: u16 },
+    /// No packet header within [`MAX_HEADER_SKIP`] bytes
+    HeaderNotFound { skipped: usize },
+    /// The package length field is too small to be valid
+    BadLength { len: u16 },
+    /// The body needs more than the `len` bytes declared by the package length
+    BodyTooShort { len: usize },
+    /// The body only used `used` of the `len` bytes declared by the package
+    /// length
+    BodyTooLong { len: usize, used: usize },
+    /// A byte (or word) that doesn't map to any value of the enum `kind`
+    UnknownValue { kind: &'static str, value: u32 },
+    /// The buffer can't hold the packet or its content
+    BufferTooSmall { needed: usize, available: usize },
+    /// A text field with non-ASCII bytes
+    InvalidText,
+}
+
+/// Encode a complete packet into the start of `out`, returning its length
+pub fn encode<T: ToWire + ?Sized>(
+    address: u32,
+    ident: PackageIdentifier,
+    content: &T,
+    out: &mut [u8],
+) -> Result<usize, FrameError> {
+    let content_len = content.size_on_wire();
+    let total = FRAME_OVERHEAD + content_len;
+    if out.len() < total {
+        return Err(FrameError::BufferTooSmall {
+            needed: total,
+            available: out.len(),
+        });
+    }
+    let out = &mut out[..total];
+
+    let rest = put(out, &HEADER);
+    let rest = put(rest, &address);
+    let rest = put(rest, &ident);
+    // content + checksum
+    let rest = put(rest, &((content_len + 2) as u16));
+    put(rest, content);
+
+    // The checksum covers everything from the identifier to the content
+    let mut cksm = Checksum::new();
+    cksm.update(&out[6..total - 2]);
+    put(&mut out[total - 2..], &cksm.finalize());
+    Ok(total)
+}
+
+/// A complete, checksum-verified packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet<'a> {
+    pub address: u32,
+    /// See [`PackageIdentifier`]
+    pub ident: u8,
+    /// Everything between the length field and the checksum
+    pub content: &'a [u8],
+    /// Number of bytes skipped before the header of this packet
+    pub skipped: usize,
+}
+
+/// Incremental packet decoder.
+///
+/// Skips anything before the 0xEF01 header, like the 0x55 sent after power
+/// on or the tail of an earlier response, and buffers one packet at a time.
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_LEN],
+    /// Bytes of the current packet in `buf`, from the header on
+    len: usize,
+    skipped: usize,
+    /// `buf` holds a complete packet, kept until the next byte comes in
+    done: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            skipped: 0,
+            done: false,
+        }
+    }
+
+    /// Feed bytes from `input`, advancing it past the bytes used.
+    ///
+    /// Stops at the end of a packet or at an error, leaving the rest of
+    /// `input` for the next call. Returns `None` once `input` is used up
+    /// without completing a packet. After an error, decoding starts over at
+    /// the next header.
+    pub fn feed(&mut self, input: &mut &[u8]) -> Option<Result<Packet<'_>, FrameError>> {
+        let res = self.advance(input)?;
+        Some(res.map(|()| self.completed()))
+    }
+
+    /// The last packet returned by [`Self::feed()`], until more bytes are fed
+    pub fn packet(&self) -> Option<Packet<'_>> {
+        self.done.then(|| self.completed())
+    }
+
+    /// The least number of bytes that can complete or fail the current
+    /// packet, so a reader never takes bytes that belong to the next one
+    pub fn bytes_needed(&self) -> usize {
+        match self.len {
+            _ if self.done => 1,
+            0..=1 => 1,
+            2..PREFIX_LEN => PREFIX_LEN - self.len,
+            _ => self.frame_len() - self.len,
+        }
+    }
+
+    /// Drop any partial packet
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.skipped = 0;
+        self.done = false;
+    }
+
+    /// [`Self::feed()`], without borrowing the packet
+    pub(crate) fn advance(&mut self, input: &mut &[u8]) -> Option<Result<(), FrameError>> {
+        while let Some((&byte, rest)) = input.split_first() {
+            *input = rest;
+            if let Some(res) = self.push(byte) {
+                return Some(res);
+            }
+        }
+        None
+    }
+
+    /// The packet in `buf`, which must be complete
+    pub(crate) fn completed(&self) -> Packet<'_> {
+        let end = self.len - 2;
+        Packet {
+            address: u32::from_be_bytes([self.buf[2], self.buf[3], self.buf[4], self.buf[5]]),
+            ident: self.buf[6],
+            content: &self.buf[PREFIX_LEN..end],
+            skipped: self.skipped,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<Result<(), FrameError>> {
+        if self.done {
+            self.reset();
+        }
+        let [hdr_hi, hdr_lo] = HEADER.to_be_bytes();
+        match self.len {
+            0 if byte != hdr_hi => return self.skip(),
+            // A repeated first header byte, skip the earlier one
+            1 if byte == hdr_hi => return self.skip(),
+            1 if byte != hdr_lo => {
+                self.len = 0;
+                if let Some(err) = self.skip() {
+                    return Some(err);
+                }
+                return self.skip();
+            }
+            _ => {}
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        if self.len == PREFIX_LEN {
+            let len = u16::from_be_bytes([self.buf[7], self.buf[8]]);
+            // checksum
+            if len < 2 {
+                self.reset();
+                return Some(Err(FrameError::BadLength { len }));
+            }
+            if self.frame_len() > MAX_FRAME_LEN {
+                self.reset();
+                return Some(Err(FrameError::BufferTooSmall {
+                    needed: len as usize - 2,
+                    available: MAX_CONTENT_LEN,
+                }));
+            }
+        }
+        if self.len > PREFIX_LEN && self.len == self.frame_len() {
+            let mut cksm = Checksum::new();
+            cksm.update(&self.buf[6..self.len - 2]);
+            let calculated = cksm.finalize();
+            let reported = u16::from_be_bytes([self.buf[self.len - 2], self.buf[self.len - 1]]);
+            if calculated != reported {
+                self.reset();
+                return Some(Err(FrameError::BadChecksum { calculated, reported }));
+            }
+            self.done = true;
+            return Some(Ok(()));
+        }
+        None
+    }
+
+    /// Count a byte that isn't part of a packet
+    fn skip(&mut self) -> Option<Result<(), FrameError>> {
+        if self.skipped == MAX_HEADER_SKIP {
+            self.reset();
+            return Some(Err(FrameError::HeaderNotFound {
+                skipped: MAX_HEADER_SKIP,
+            }));
+        }
+        self.skipped += 1;
+        None
+    }
+
+    /// Length of the whole packet, once the length field is in
+    fn frame_len(&self) -> usize {
+        PREFIX_LEN + u16::from_be_bytes([self.buf[7], self.buf[8]]) as usize
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::{
+    constants::{IndexTableIdx, ModelId},
+    timeout::DelaySource,
+    Error, R503,
+};
+
+/// Number of index table pages
+pub const INDEX_PAGES: usize = 4;
+/// Size of a single index table page, in bytes
+pub const INDEX_PAGE_SIZE: usize = 32;
+/// Slots covered by one index table page
+pub const SLOTS_PER_PAGE: u16 = INDEX_PAGE_SIZE as u16 * 8;
+/// Largest library the index table can describe
+pub const MAX_SLOTS: u16 = INDEX_PAGES as u16 * SLOTS_PER_PAGE;
+
+/// Which library slots hold a template, read from the index table.
+///
+/// Each page covers 256 slots, 8 per byte. Within a byte, bit 0 (the least
+/// significant) is the lowest slot: bit `k` of byte `j` on page `i` is slot
+/// `256 * i + 8 * j + k`. The manual draws each byte from bit 7 down, which
+/// reads as if bit 7 came first, but modules set bit 0 for the lowest slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateIndex {
+    bits: [u8; INDEX_PAGES * INDEX_PAGE_SIZE],
+    capacity: u16,
+}
+
+/// A slot that differs between two [`TemplateIndex`] snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexChange {
+    Added(ModelId),
+    Removed(ModelId),
+}
+
+impl TemplateIndex {
+    /// An index for a library of `capacity` slots, from the pages read so
+    /// far, starting at page 0. Missing pages are empty, and bits past the
+    /// capacity are ignored. `None` if `capacity` is over [`MAX_SLOTS`].
+    pub fn from_pages(capacity: u16, pages: &[[u8; INDEX_PAGE_SIZE]]) -> Option<Self> {
+        (capacity <= MAX_SLOTS).then(|| Self::from_pages_unchecked(capacity, pages))
+    }
+
+    /// [`Self::from_pages()`], for a capacity already known to fit
+    fn from_pages_unchecked(capacity: u16, pages: &[[u8; INDEX_PAGE_SIZE]]) -> Self {
+        let mut bits = [0u8; INDEX_PAGES * INDEX_PAGE_SIZE];
+        for (chunk, page) in bits.chunks_mut(INDEX_PAGE_SIZE).zip(pages) {
+            chunk.copy_from_slice(page);
+        }
+        let mut index = Self { bits, capacity };
+        for id in capacity..index.bits.len() as u16 * 8 {
+            index.bits[usize::from(id / 8)] &= !(1 << (id % 8));
+        }
+        index
+    }
+
+    /// Number of slots in the library
+    pub fn capacity(&self) -> u16 {
+        self.capacity
+    }
+
+    pub fn is_occupied(&self, id: ModelId) -> bool {
+        self.bit(id.get())
+    }
+
+    /// Occupied slots, lowest first
+    pub fn iter_occupied(&self) -> impl Iterator<Item = ModelId> + '_ {
+        self.ids().filter(|id| self.is_occupied(*id))
+    }
+
+    /// The lowest empty slot, if the library isn't full
+    pub fn first_free(&self) -> Option<ModelId> {
+        self.ids().find(|id| !self.is_occupied(*id))
+    }
+
+    /// Number of stored templates
+    pub fn count(&self) -> u16 {
+        self.bits.iter().map(|b| b.count_ones() as u16).sum()
+    }
+
+    /// Slots filled or emptied since `previous`, lowest first
+    pub fn diff<'a>(&'a self, previous: &'a TemplateIndex) -> impl Iterator<Item = IndexChange> + 'a {
+        let capacity = self.capacity.max(previous.capacity);
+        (0..capacity).filter_map(move |id| {
+            let model_id = ModelId::from_raw(id);
+            match (previous.bit(id), self.bit(id)) {
+                (false, true) => Some(IndexChange::Added(model_id)),
+                (true, false) => Some(IndexChange::Removed(model_id)),
+                _ => None,
+            }
+        })
+    }
+
+    fn ids(&self) -> impl Iterator<Item = ModelId> {
+        (0..self.capacity).map(ModelId::from_raw)
+    }
+
+    fn bit(&self, id: u16) -> bool {
+        id < self.capacity && self.bits[usize::from(id / 8)] & (1 << (id % 8)) != 0
+    }
+}
+
+impl<D: DelaySource> R503<D> {
+    /// Read the index table pages covering the whole library, as sized by
+    /// the system parameters.
+    ///
+    /// Returns [`Error::BufferTooSmall`] for a library over [`MAX_SLOTS`],
+    /// which the index table can't describe.
+    pub async fn read_template_index<S>(&self, serial: &mut S) -> Result<TemplateIndex, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let capacity = self.read_system_parameter(serial).await?.library_size;
+        if capacity > MAX_SLOTS {
+            return Err(Error::BufferTooSmall {
+                needed: capacity.into(),
+                available: MAX_SLOTS.into(),
+            });
+        }
+        let used = usize::from(capacity.div_ceil(SLOTS_PER_PAGE));
+        let mut pages = [[0u8; INDEX_PAGE_SIZE]; INDEX_PAGES];
+        for (i, page) in pages.iter_mut().enumerate().take(used) {
+            let idx = IndexTableIdx::try_from(i as u8).map_err(|_| Error::UnknownValue {
+                kind: "IndexTableIdx",
+                value: i as u32,
+            })?;
+            *page = self.read_idx_table(serial, idx).await?;
+        }
+        Ok(TemplateIndex::from_pages_unchecked(capacity, &pages[..used]))
+    }
+}
@@ -1,13 +1,42 @@
 #![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use core::fmt::Debug;
+use core::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use constants::{AuraControlPayload, CharBufferId, Commands, ConfirmationCode, IndexTableIdx, PackageIdentifier};
+use constants::{
+    AuraControlPayload, BaudRate, CharBufferId, Commands, ConfirmationCode, IndexTableIdx, ModelId, PackageIdentifier,
+    PacketLength, Password, SecurityLevel, StatusRegister, SystemParameter, PACKED_IMAGE_SIZE, READY,
+};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use frame::{Decoder, FrameError, Packet, MAX_FRAME_LEN};
 use embedded_io_async::{ErrorType, Read, ReadExactError, Write};
-use wire_traits::{FromWire, ToWire};
+use heapless::String;
+use notepad::{NotepadPage, WriteNotepadRequest, NOTEPAD_PAGE_SIZE};
+use power::{Clock, PowerControl, PowerError, READY_TIMEOUT_MS};
+use retry::{Idempotency, RetryPolicy};
+use timeout::{
+    drain, resync_on_timeout, with_timeout, DelaySource, NoTimeout, CANCEL_TIMEOUT_MS, DATA_PACKET_TIMEOUT_MS,
+    DEFAULT_TIMEOUT_MS,
+};
+use wire_traits::{put, FromWire, ToWire};
 
 pub mod auto;
+pub mod blocking;
 pub mod constants;
+pub mod enroll;
+pub mod frame;
+pub mod index;
+pub mod notepad;
+pub mod power;
+pub mod retry;
+#[cfg(feature = "std")]
+pub mod std_serial;
+pub mod timeout;
+pub mod touch;
+pub mod verify;
 pub mod wire_traits;
 
 //////////////////////////////////////////////////////////////////////////////
@@ -19,10 +48,109 @@ where
     S: ErrorType,
 {
     Wire(S::Error),
-    IncorrectData,
     EndOfFile,
     BadConfirmation(ConfirmationCode),
-    BadChecksum,
+    BadChecksum {
+        calculated: u16,
+        reported: u16,
+    },
+    /// No response in time. The link has been resynced, see [`R503::resync()`]
+    Timeout,
+    /// No packet header within [`MAX_HEADER_SKIP`] bytes
+    HeaderNotFound {
+        skipped: usize,
+    },
+    /// The packet came from a different module
+    WrongAddress {
+        expected: u32,
+        actual: u32,
+    },
+    /// Unexpected package identifier, see [`PackageIdentifier`]
+    WrongIdentifier {
+        expected: u8,
+        actual: u8,
+    },
+    /// The package length field is too small to be valid
+    BadLength {
+        len: u16,
+    },
+    /// The body needs more than the `len` bytes declared by the package length
+    BodyTooShort {
+        len: usize,
+    },
+    /// The body only used `used` of the `len` bytes declared by the package
+    /// length
+    BodyTooLong {
+        len: usize,
+        used: usize,
+    },
+    /// A byte (or word) that doesn't map to any value of the enum `kind`
+    UnknownValue {
+        kind: &'static str,
+        value: u32,
+    },
+    /// The output buffer can't hold the incoming data
+    BufferTooSmall {
+        needed: usize,
+        available: usize,
+    },
+    /// Data to send, or data received, has the wrong size
+    WrongSize {
+        expected: usize,
+        actual: usize,
+    },
+    /// AutoIdentify reported a different step than expected
+    WrongStep {
+        expected: u8,
+        actual: u8,
+    },
+    /// A text field with non-ASCII bytes
+    InvalidText,
+    /// The module reports a different baud rate than the one just set
+    WrongBaudRate {
+        expected: BaudRate,
+        actual: BaudRate,
+    },
+}
+
+impl<S: ErrorType> Error<S> {
+    /// The same error, for another port with the same error type
+    pub(crate) fn cast<S2: ErrorType<Error = S::Error>>(self) -> Error<S2> {
+        match self {
+            Error::Wire(w) => Error::Wire(w),
+            Error::EndOfFile => Error::EndOfFile,
+            Error::BadConfirmation(c) => Error::BadConfirmation(c),
+            Error::BadChecksum { calculated, reported } => Error::BadChecksum { calculated, reported },
+            Error::Timeout => Error::Timeout,
+            Error::HeaderNotFound { skipped } => Error::HeaderNotFound { skipped },
+            Error::WrongAddress { expected, actual } => Error::WrongAddress { expected, actual },
+            Error::WrongIdentifier { expected, actual } => Error::WrongIdentifier { expected, actual },
+            Error::BadLength { len } => Error::BadLength { len },
+            Error::BodyTooShort { len } => Error::BodyTooShort { len },
+            Error::BodyTooLong { len, used } => Error::BodyTooLong { len, used },
+            Error::UnknownValue { kind, value } => Error::UnknownValue { kind, value },
+            Error::BufferTooSmall { needed, available } => Error::BufferTooSmall { needed, available },
+            Error::WrongSize { expected, actual } => Error::WrongSize { expected, actual },
+            Error::WrongStep { expected, actual } => Error::WrongStep { expected, actual },
+            Error::InvalidText => Error::InvalidText,
+            Error::WrongBaudRate { expected, actual } => Error::WrongBaudRate { expected, actual },
+        }
+    }
+}
+
+impl<S: ErrorType> From<FrameError> for Error<S> {
+    fn from(value: FrameError) -> Self {
+        match value {
+            FrameError::BadChecksum { calculated, reported } => Error::BadChecksum { calculated, reported },
+            FrameError::HeaderNotFound { skipped } => Error::HeaderNotFound { skipped },
+            FrameError::BadLength { len } => Error::BadLength { len },
+            FrameError::BodyTooShort { len } => Error::BodyTooShort { len },
+            FrameError::BodyTooLong { len, used } => Error::BodyTooLong { len, used },
+            FrameError::UnknownValue { kind, value } => Error::UnknownValue { kind, value },
+            FrameError::BufferTooSmall { needed, available } => Error::BufferTooSmall { needed, available },
+            FrameError::InvalidText => Error::InvalidText,
+        }
+    }
 }
 
 impl<S> Debug for Error<S>
@@ -38,7 +166,6 @@ where
                 f.write_str(")")?;
                 Ok(())
             }
-            Error::IncorrectData => f.write_str("Error::IncorrectData"),
             Error::EndOfFile => f.write_str("Error::EndOfFile"),
             Error::BadConfirmation(c) => {
                 f.write_str("Error::BadConfirmation(")?;
@@ -46,11 +173,117 @@ where
                 f.write_str(")")?;
                 Ok(())
             }
-            Error::BadChecksum => f.write_str("Error::BadChecksum"),
+            Error::BadChecksum { calculated, reported } => f
+                .debug_struct("Error::BadChecksum")
+                .field("calculated", calculated)
+                .field("reported", reported)
+                .finish(),
+            Error::Timeout => f.write_str("Error::Timeout"),
+            Error::HeaderNotFound { skipped } => f
+                .debug_struct("Error::HeaderNotFound")
+                .field("skipped", skipped)
+                .finish(),
+            Error::WrongAddress { expected, actual } => f
+                .debug_struct("Error::WrongAddress")
+                .field("expected", expected)
+                .field("actual", actual)
+                .finish(),
+            Error::WrongIdentifier { expected, actual } => f
+                .debug_struct("Error::WrongIdentifier")
+                .field("expected", expected)
+                .field("actual", actual)
+                .finish(),
+            Error::BadLength { len } => f.debug_struct("Error::BadLength").field("len", len).finish(),
+            Error::BodyTooShort { len } => f.debug_struct("Error::BodyTooShort").field("len", len).finish(),
+            Error::BodyTooLong { len, used } => f
+                .debug_struct("Error::BodyTooLong")
+                .field("len", len)
+                .field("used", used)
+                .finish(),
+            Error::UnknownValue { kind, value } => f
+                .debug_struct("Error::UnknownValue")
+                .field("kind", kind)
+                .field("value", value)
+                .finish(),
+            Error::BufferTooSmall { needed, available } => f
+                .debug_struct("Error::BufferTooSmall")
+                .field("needed", needed)
+                .field("available", available)
+                .finish(),
+            Error::WrongSize { expected, actual } => f
+                .debug_struct("Error::WrongSize")
+                .field("expected", expected)
+                .field("actual", actual)
+                .finish(),
+            Error::WrongStep { expected, actual } => f
+                .debug_struct("Error::WrongStep")
+                .field("expected", expected)
+                .field("actual", actual)
+                .finish(),
+            Error::InvalidText => f.write_str("Error::InvalidText"),
+            Error::WrongBaudRate { expected, actual } => f
+                .debug_struct("Error::WrongBaudRate")
+                .field("expected", expected)
+                .field("actual", actual)
+                .finish(),
+        }
+    }
+}
+
+impl<S> Display for Error<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Wire(w) => write!(f, "serial port error: {w:?}"),
+            Error::EndOfFile => f.write_str("serial port closed"),
+            Error::BadConfirmation(c) => write!(f, "module replied with {c:?}"),
+            Error::BadChecksum { calculated, reported } => {
+                write!(f, "bad checksum: calculated {calculated:#06X}, packet says {reported:#06X}")
+            }
+            Error::Timeout => f.write_str("timed out waiting for the module"),
+            Error::HeaderNotFound { skipped } => write!(f, "no packet header in {skipped} bytes"),
+            Error::WrongAddress { expected, actual } => {
+                write!(f, "packet from address {actual:#010X}, expected {expected:#010X}")
+            }
+            Error::WrongIdentifier { expected, actual } => {
+                write!(f, "package identifier {actual:#04X}, expected {expected:#04X}")
+            }
+            Error::BadLength { len } => write!(f, "invalid package length {len}"),
+            Error::BodyTooShort { len } => write!(f, "response body truncated to {len} bytes"),
+            Error::BodyTooLong { len, used } => {
+                write!(f, "response body has {len} bytes, only {used} expected")
+            }
+            Error::UnknownValue { kind, value } => write!(f, "unknown {kind} {value:#X}"),
+            Error::BufferTooSmall { needed, available } => {
+                write!(f, "buffer too small: {needed} bytes needed, {available} available")
+            }
+            Error::WrongSize { expected, actual } => {
+                write!(f, "wrong size: {actual} bytes, expected {expected}")
+            }
+            Error::WrongStep { expected, actual } => {
+                write!(f, "module reported step {actual:#04X}, expected {expected:#04X}")
+            }
+            Error::InvalidText => f.write_str("text field is not ASCII"),
+            Error::WrongBaudRate { expected, actual } => write!(
+                f,
+                "module runs at {} bps, expected {} bps",
+                actual.bits_per_second(),
+                expected.bits_per_second()
+            ),
         }
     }
 }
 
+impl<S> core::error::Error for Error<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // Command Packet Type
 //////////////////////////////////////////////////////////////////////////////
@@ -62,38 +295,59 @@ pub struct Command<T: ToWire> {
 }
 
 impl<T: ToWire> Command<T> {
+    pub fn new(address: u32, instruction: Commands, body: T) -> Self {
+        Self {
+            address,
+            instruction,
+            body,
+        }
+    }
+
+    /// Encode the whole packet into the start of `out`, returning its length
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, FrameError> {
+        let content = (self.instruction, &self.body);
+        frame::encode(self.address, PackageIdentifier::CommandPacket, &content, out)
+    }
+
     pub async fn to_wire<S>(&self, serial: &mut S) -> Result<(), Error<S>>
     where
         S: Write + ErrorType,
     {
-        // Header
-        0xEF01u16.to_wire(serial, None).await?;
-        // Adder
-        self.address.to_wire(serial, None).await?;
-
-        // CRC starts here!
-        let mut crc = Checksum::new();
-
-        // Package Identifier
-        PackageIdentifier::CommandPacket
-            .to_wire(serial, Some(&mut crc))
-            .await?;
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let len = self.encode(&mut buf)?;
+        serial.write_all(&buf[..len]).await.map_err(Error::Wire)
+    }
+}
 
-        // length
-        let blen = self.body.size_on_wire();
-        // command + CRC
-        ((3 + blen) as u16).to_wire(serial, Some(&mut crc)).await?;
+//////////////////////////////////////////////////////////////////////////////
+// Data Packet Type
+//////////////////////////////////////////////////////////////////////////////
 
-        // command
-        self.instruction.to_wire(serial, Some(&mut crc)).await?;
+pub struct DataPacket<'a> {
+    address: u32,
+    ident: PackageIdentifier,
+    data: &'a [u8],
+}
 
-        // body (optional)
-        self.body.to_wire(serial, Some(&mut crc)).await?;
+impl<'a> DataPacket<'a> {
+    /// `ident` is either [`PackageIdentifier::DataPacket`] or
+    /// [`PackageIdentifier::EndOfDataPacket`] for the last one
+    pub fn new(address: u32, ident: PackageIdentifier, data: &'a [u8]) -> Self {
+        Self { address, ident, data }
+    }
 
-        // CRC
-        crc.finalize().to_wire(serial, None).await?;
+    /// Encode the whole packet into the start of `out`, returning its length
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, FrameError> {
+        frame::encode(self.address, self.ident, self.data, out)
+    }
 
-        Ok(())
+    pub async fn to_wire<S>(&self, serial: &mut S) -> Result<(), Error<S>>
+    where
+        S: Write + ErrorType,
+    {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let len = self.encode(&mut buf)?;
+        serial.write_all(&buf[..len]).await.map_err(Error::Wire)
     }
 }
 
@@ -101,11 +355,43 @@ impl<T: ToWire> Command<T> {
 // Acknowledge Packet Type
 //////////////////////////////////////////////////////////////////////////////
 
+/// Give up looking for a packet header after skipping this many bytes
+pub const MAX_HEADER_SKIP: usize = 512;
+
+/// Fill `buf` from `serial`
+async fn read_exact<S: ErrorType + Read>(serial: &mut S, buf: &mut [u8]) -> Result<(), Error<S>> {
+    match serial.read_exact(buf).await {
+        Ok(()) => Ok(()),
+        Err(ReadExactError::UnexpectedEof) => Err(Error::EndOfFile),
+        Err(ReadExactError::Other(w)) => Err(Error::Wire(w)),
+    }
+}
+
+/// Read one complete, checksum-verified packet into `decoder`.
+///
+/// Never reads past the end of the packet, so the next one starts in sync.
+async fn recv_packet<'d, S: ErrorType + Read>(
+    serial: &mut S,
+    decoder: &'d mut Decoder,
+) -> Result<Packet<'d>, Error<S>> {
+    let mut buf = [0u8; 32];
+    loop {
+        let now = &mut buf[..decoder.bytes_needed().min(32)];
+        read_exact(serial, now).await?;
+        if let Some(res) = decoder.advance(&mut &now[..]) {
+            res?;
+            return Ok(decoder.completed());
+        }
+    }
+}
+
 pub struct Response<T> {
     address: u32,
     ident: u8,
     confirmation: ConfirmationCode,
-    body: T,
+    /// Only present with [`ConfirmationCode::SuccessCode`]
+    body: Option<T>,
+    skipped: usize,
 }
 
 impl<T> Response<T> {
@@ -113,35 +399,113 @@ impl<T> Response<T> {
     where
         T: FromWire,
     {
-        // Do we have the right header?
-        let hdr = u16::from_wire(serial, None).await?;
-        if hdr != 0xEF01 {
-            return Err(Error::IncorrectData);
-        }
-
-        let address = u32::from_wire(serial, None).await?;
-
-        // The remaining bits are checksum relevant!
-        let mut cksm = Checksum::new();
-        let ident = u8::from_wire(serial, Some(&mut cksm)).await?;
-        // TODO: check len?
-        let _len = u16::from_wire(serial, Some(&mut cksm)).await?;
-        let confirmation = ConfirmationCode::from_wire(serial, Some(&mut cksm)).await?;
-        let body = T::from_wire(serial, Some(&mut cksm)).await?;
-
-        let calc_cksm = cksm.finalize();
-        let rept_cksm = u16::from_wire(serial, None).await?;
+        let mut decoder = Decoder::new();
+        let packet = recv_packet(serial, &mut decoder).await?;
+        Ok(Self::from_packet(&packet)?)
+    }
 
-        if calc_cksm != rept_cksm {
-            return Err(Error::BadChecksum);
+    /// Parse the confirmation code and body of a decoded packet
+    pub fn from_packet(packet: &Packet<'_>) -> Result<Self, FrameError>
+    where
+        T: FromWire,
+    {
+        let mut content = packet.content;
+        // confirmation code + checksum
+        if content.is_empty() {
+            return Err(FrameError::BadLength { len: 2 });
         }
+        let confirmation = ConfirmationCode::from_wire(&mut content)?;
+
+        let body = if confirmation == ConfirmationCode::SuccessCode {
+            let len = content.len();
+            let body = T::from_wire(&mut content).map_err(|e| match e {
+                // The body needs more bytes than the packet has
+                FrameError::BodyTooShort { .. } => FrameError::BodyTooShort { len },
+                e => e,
+            })?;
+            if !content.is_empty() {
+                return Err(FrameError::BodyTooLong {
+                    len,
+                    used: len - content.len(),
+                });
+            }
+            Some(body)
+        } else {
+            // Error confirmations often come without a body
+            None
+        };
         Ok(Self {
-            address,
-            ident,
+            address: packet.address,
+            ident: packet.ident,
             confirmation,
             body,
+            skipped: packet.skipped,
         })
     }
+
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    pub fn confirmation(&self) -> ConfirmationCode {
+        self.confirmation
+    }
+
+    pub fn body(&self) -> Option<&T> {
+        self.body.as_ref()
+    }
+
+    /// Number of bytes skipped before the header of this packet
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Check that this is a successful acknowledgement from `address`, and
+    /// return its body
+    pub(crate) fn into_ack_body<S: ErrorType>(self, address: u32) -> Result<T, Error<S>> {
+        if self.address != address {
+            return Err(Error::WrongAddress {
+                expected: address,
+                actual: self.address,
+            });
+        }
+        let ack = PackageIdentifier::AcknowledgePacket.into();
+        if self.ident != ack {
+            return Err(Error::WrongIdentifier {
+                expected: ack,
+                actual: self.ident,
+            });
+        }
+        if self.confirmation != ConfirmationCode::SuccessCode {
+            return Err(Error::BadConfirmation(self.confirmation));
+        }
+        // Always present on success
+        self.body.ok_or(Error::BodyTooShort { len: 0 })
+    }
+}
+
+/// Read one packet of any kind, discarding the body.
+///
+/// Returns the response (with the first body byte as confirmation code, if it
+/// is an acknowledgement) and the value of the length field.
+async fn discard_packet<S: ErrorType + Read>(serial: &mut S) -> Result<(Response<()>, u16), Error<S>> {
+    let mut decoder = Decoder::new();
+    let packet = recv_packet(serial, &mut decoder).await?;
+
+    let mut confirmation = ConfirmationCode::SystemReserved;
+    if packet.ident == PackageIdentifier::AcknowledgePacket.into() {
+        if let Some(&byte) = packet.content.first() {
+            confirmation = ConfirmationCode::try_from(byte).unwrap_or(ConfirmationCode::SystemReserved);
+        }
+    }
+    let resp = Response {
+        address: packet.address,
+        ident: packet.ident,
+        confirmation,
+        body: Some(()),
+        skipped: packet.skipped,
+    };
+    Ok((resp, (packet.content.len() + 2) as u16))
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -178,19 +542,304 @@ impl Default for Checksum {
 // R503
 //////////////////////////////////////////////////////////////////////////////
 
-pub struct R503 {
+/// A handle to a module at a given address.
+///
+/// Without a delay source every command waits forever for its response, see
+/// [`Self::with_delay()`] to add timeouts.
+pub struct R503<D: DelaySource = NoTimeout> {
     address: u32,
+    packet_length: PacketLength,
+    delay: D,
+    retry: RetryPolicy,
+    skipped: AtomicUsize,
 }
 
 impl R503 {
     pub fn new_with_address(addr: u32) -> Self {
-        Self { address: addr }
+        Self {
+            address: addr,
+            // "The value is 128 Bytes before delivery"
+            packet_length: PacketLength::Bytes128,
+            delay: NoTimeout,
+            retry: RetryPolicy::NONE,
+            skipped: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<D: DelaySource> R503<D> {
+    /// Time out commands using `delay`.
+    ///
+    /// Each command has its own timeout, most use [`DEFAULT_TIMEOUT_MS`].
+    /// When a timeout fires, the line is drained before returning
+    /// [`Error::Timeout`], so the next command starts in sync.
+    pub fn with_delay<D2: DelaySource>(self, delay: D2) -> R503<D2> {
+        R503 {
+            address: self.address,
+            packet_length: self.packet_length,
+            delay,
+            retry: self.retry,
+            skipped: self.skipped,
+        }
+    }
+
+    pub fn delay(&self) -> &D {
+        &self.delay
+    }
+
+    /// Retry commands after transient failures, see [`RetryPolicy`]
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Number of stray bytes skipped while looking for packet headers since
+    /// the last call, useful for logging line noise
+    pub fn take_skipped_bytes(&self) -> usize {
+        self.skipped.swap(0, Ordering::Relaxed)
     }
 
     pub fn address(&self) -> u32 {
         self.address
     }
 
+    /// The data packet length used when sending data to the module.
+    ///
+    /// This must match the module's configured packet length, see
+    /// [`Self::sync_packet_length()`] and [`Self::reconfigure_packet_length()`].
+    pub fn packet_length(&self) -> PacketLength {
+        self.packet_length
+    }
+
+    pub fn set_packet_length(&mut self, packet_length: PacketLength) {
+        self.packet_length = packet_length;
+    }
+
+    /// Discard anything the module is still sending, until the line has been
+    /// quiet for a moment. Returns the number of bytes discarded.
+    ///
+    /// Use this to recover after an error left a response half read. Does
+    /// nothing without a delay source.
+    pub async fn resync<S: Read + ErrorType>(&self, serial: &mut S) -> Result<usize, Error<S>> {
+        drain(&self.delay, serial).await
+    }
+
+    /// Send a command, and wait up to `timeout_ms` for the acknowledgement,
+    /// retrying as the [`RetryPolicy`] allows
+    async fn command_with_ack<S, T, R>(
+        &self,
+        serial: &mut S,
+        instruction: Commands,
+        timeout_ms: u32,
+        idempotency: Idempotency,
+        body: T,
+    ) -> Result<R, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+        T: ToWire,
+        R: FromWire,
+    {
+        let cmd = Command {
+            address: self.address,
+            instruction,
+            body,
+        };
+        let mut attempt = 1;
+        loop {
+            cmd.to_wire(serial).await?;
+            let err = match self.recv_ack(serial, self.address, timeout_ms).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+            if attempt >= self.retry.max_attempts || !self.retry.should_retry(&err, idempotency) {
+                return Err(err);
+            }
+            // Timeouts have already been resynced
+            if !matches!(err, Error::Timeout) {
+                self.resync(serial).await?;
+            }
+            let backoff_ms = self.retry.backoff_for(attempt);
+            if backoff_ms != 0 && self.delay.is_enabled() {
+                self.delay.clone().delay_ms(backoff_ms).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Wait up to `timeout_ms` for an acknowledgement from `address`
+    async fn recv_ack<S, R>(&self, serial: &mut S, address: u32, timeout_ms: u32) -> Result<R, Error<S>>
+    where
+        S: Read + ErrorType,
+        R: FromWire,
+    {
+        let res = with_timeout(&self.delay, timeout_ms, Response::<R>::from_wire(serial)).await;
+        let resp = resync_on_timeout(&self.delay, serial, res).await?;
+        self.skipped.fetch_add(resp.skipped, Ordering::Relaxed);
+
+        resp.into_ack_body(address)
+    }
+
+    /// Send `data` to the module as a series of data packets, each at most
+    /// [`Self::packet_length()`] bytes long, followed by an end of data packet.
+    async fn send_data<S: Write + ErrorType>(
+        &self,
+        serial: &mut S,
+        data: &[u8],
+    ) -> Result<(), Error<S>> {
+        let mut remain = data;
+        loop {
+            let (now, later) = remain.split_at(remain.len().min(self.packet_length.bytes()));
+            remain = later;
+            let ident = if remain.is_empty() {
+                PackageIdentifier::EndOfDataPacket
+            } else {
+                PackageIdentifier::DataPacket
+            };
+            DataPacket::new(self.address, ident, now).to_wire(serial).await?;
+            if remain.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Download a template (usually 512 bytes) from the host into `char_buffer`
+    ///
+    /// This is the counterpart of [`Self::upload_template()`] followed by
+    /// [`Self::stream_image()`].
+    pub async fn download_template<S>(
+        &self,
+        serial: &mut S,
+        char_buffer: CharBufferId,
+        template: &[u8],
+    ) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.command_with_ack::<_, _, ()>(
+            serial,
+            Commands::DownChar,
+            DEFAULT_TIMEOUT_MS,
+            Idempotency::NotIdempotent,
+            char_buffer,
+        )
+        .await?;
+        self.send_data(serial, template).await
+    }
+
+    /// Cancel a running AutoEnroll or AutoIdentify.
+    ///
+    /// Any acknowledgements still sent by the running operation are read and
+    /// discarded, up to and including the acknowledgement of the Cancel
+    /// command itself, so the link stays in sync. With a delay source, this
+    /// gives up with [`Error::Timeout`] after [`CANCEL_TIMEOUT_MS`].
+    pub async fn cancel<S>(&self, serial: &mut S) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let cmd = Command {
+            address: self.address,
+            instruction: Commands::Cancel,
+            body: (),
+        };
+        cmd.to_wire(serial).await?;
+
+        let fut = async {
+            loop {
+                let (resp, len) = discard_packet(serial).await?;
+                self.skipped.fetch_add(resp.skipped, Ordering::Relaxed);
+                let is_ack = resp.ident == PackageIdentifier::AcknowledgePacket.into();
+                // The Cancel acknowledgement is just the confirmation code,
+                // either success or a garbled command. Anything else, like
+                // the step or error acknowledgements of the operation being
+                // cancelled, is discarded.
+                if resp.address != self.address || !is_ack || len != 3 {
+                    continue;
+                }
+                match resp.confirmation {
+                    ConfirmationCode::SuccessCode => return Ok(()),
+                    ConfirmationCode::ErrorCode => return Err(Error::BadConfirmation(resp.confirmation)),
+                    _ => continue,
+                }
+            }
+        };
+        let res = with_timeout(&self.delay, CANCEL_TIMEOUT_MS, fut).await;
+        resync_on_timeout(&self.delay, serial, res).await
+    }
+
+    /// Read the 512 byte information page into `out_buf`, returning the number
+    /// of bytes received
+    pub async fn read_info_page<S>(&self, serial: &mut S, out_buf: &mut [u8]) -> Result<usize, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.command_with_ack::<_, _, ()>(
+            serial,
+            Commands::ReadInfPage,
+            DEFAULT_TIMEOUT_MS,
+            Idempotency::NotIdempotent,
+            (),
+        )
+        .await?;
+        self.stream_image(serial, out_buf).await
+    }
+
+    /// Change the module's address, returning a handle bound to it with the
+    /// same settings.
+    ///
+    /// The new address is stored in the module's flash, and the module replies
+    /// from the new address. On failure the old handle is handed back with the
+    /// error, still bound to the old address.
+    pub async fn set_address<S>(self, serial: &mut S, address: u32) -> Result<R503<D>, (Self, Error<S>)>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let cmd = Command {
+            address: self.address,
+            instruction: Commands::SetAdder,
+            body: address,
+        };
+        let res = match cmd.to_wire(serial).await {
+            Ok(()) => self.recv_ack::<_, ()>(serial, address, DEFAULT_TIMEOUT_MS).await,
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(()) => Ok(Self { address, ..self }),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    /// Download a packed 4-bit image from the host into the ImageBuffer
+    ///
+    /// `image` must be exactly [`PACKED_IMAGE_SIZE`] bytes, in the same format
+    /// produced by [`Self::upload_image()`] followed by [`Self::stream_image()`].
+    pub async fn download_image<S>(&self, serial: &mut S, image: &[u8]) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        if image.len() != PACKED_IMAGE_SIZE {
+            return Err(Error::WrongSize {
+                expected: PACKED_IMAGE_SIZE,
+                actual: image.len(),
+            });
+        }
+        self.command_with_ack::<_, _, ()>(
+            serial,
+            Commands::DownImage,
+            DEFAULT_TIMEOUT_MS,
+            Idempotency::NotIdempotent,
+            (),
+        )
+        .await?;
+        self.send_data(serial, image).await
+    }
+
+    /// Receive the data packets that follow an upload command into `out_buf`,
+    /// returning the number of bytes received
+    ///
+    /// Each packet must arrive within [`DATA_PACKET_TIMEOUT_MS`].
     pub async fn stream_image<S: Read + ErrorType>(
         &self,
         serial: &mut S,
@@ -200,72 +849,303 @@ impl R503 {
         let ttl_len = out_buf.len();
         let mut window = out_buf;
         while more {
-            // Do we have the right header?
-            let hdr = u16::from_wire(serial, None).await?;
-            if hdr != 0xEF01 {
-                return Err(Error::IncorrectData);
-            }
+            let res = with_timeout(&self.delay, DATA_PACKET_TIMEOUT_MS, self.recv_data_packet(serial, window)).await;
+            let (used, last) = resync_on_timeout(&self.delay, serial, res).await?;
+            window = &mut core::mem::take(&mut window)[used..];
+            more = !last;
+        }
+        let used = ttl_len - window.len();
+        Ok(used)
+    }
 
-            let address = u32::from_wire(serial, None).await?;
-            if address != self.address {
-                return Err(Error::IncorrectData);
-            }
+    /// Receive a single data packet into the start of `out_buf`, returning the
+    /// number of bytes received and whether it was the end of data packet
+    async fn recv_data_packet<S: Read + ErrorType>(
+        &self,
+        serial: &mut S,
+        out_buf: &mut [u8],
+    ) -> Result<(usize, bool), Error<S>> {
+        let mut decoder = Decoder::new();
+        let packet = recv_packet(serial, &mut decoder).await?;
+        self.skipped.fetch_add(packet.skipped, Ordering::Relaxed);
 
-            // The remaining bits are checksum relevant!
-            let mut cksm = Checksum::new();
-            let ident = u8::from_wire(serial, Some(&mut cksm)).await?;
+        if packet.address != self.address {
+            return Err(Error::WrongAddress {
+                expected: self.address,
+                actual: packet.address,
+            });
+        }
 
-            match ident {
-                0x02 => {
-                    // "Have following packet"
-                }
-                0x08 => {
-                    // "end packet"
-                    more = false;
-                }
-                _ => return Err(Error::IncorrectData),
+        let last = match packet.ident {
+            // "Have following packet"
+            0x02 => false,
+            // "end packet"
+            0x08 => true,
+            _ => {
+                return Err(Error::WrongIdentifier {
+                    expected: PackageIdentifier::DataPacket.into(),
+                    actual: packet.ident,
+                })
             }
+        };
+
+        let len_img = packet.content.len();
+        if out_buf.len() < len_img {
+            return Err(Error::BufferTooSmall {
+                needed: len_img,
+                available: out_buf.len(),
+            });
+        }
+        out_buf[..len_img].copy_from_slice(packet.content);
+        Ok((len_img, last))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Baud rate migration
+//////////////////////////////////////////////////////////////////////////////
+
+/// Implemented by host serial ports that can change their own baud rate
+pub trait BaudRateControl: ErrorType {
+    /// Retune the host side of the UART to `baud_rate`
+    fn set_baud_rate(
+        &mut self,
+        baud_rate: BaudRate,
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>>;
+}
+
+impl<D: DelayNs + Clone> R503<D> {
+    /// Change the module's baud rate, and move the host UART along with it.
+    ///
+    /// This reads the current settings, sends SetSysPara, retunes `serial`,
+    /// then confirms the link by reading the settings back at the new rate.
+    /// ReadSysPara is used rather than HandShake, as it also reports the rate
+    /// the module has stored. If the module doesn't answer at the new rate,
+    /// `serial` is moved back to the old rate and the link is checked again
+    /// there, so that the module is never left at a rate the host doesn't
+    /// know about. In that case the error from the new rate is returned.
+    ///
+    /// Modules that only apply the new rate after a power cycle will answer at
+    /// the old rate, and this returns the error seen at the new rate. Only
+    /// handles with a delay source have this, as the confirmation must time
+    /// out when the module is silent at the new rate.
+    pub async fn reconfigure_baud_rate<S>(
+        &self,
+        serial: &mut S,
+        baud_rate: BaudRate,
+    ) -> Result<(), Error<S>>
+    where
+        S: Read + Write + BaudRateControl,
+    {
+        self.migrate_baud_rate(serial, baud_rate).await
+    }
+}
+
+impl<D: DelaySource> R503<D> {
+    /// See [`Self::reconfigure_baud_rate()`]. Without a delay source this
+    /// relies on the port's own read timeout.
+    pub(crate) async fn migrate_baud_rate<S>(
+        &self,
+        serial: &mut S,
+        baud_rate: BaudRate,
+    ) -> Result<(), Error<S>>
+    where
+        S: Read + Write + BaudRateControl,
+    {
+        let old = self.read_system_parameter(serial).await?.baud_rate;
+        self.set_system_parameter(serial, SystemParameter::BaudRate(baud_rate))
+            .await?;
+        serial.set_baud_rate(baud_rate).await.map_err(Error::Wire)?;
+
+        let err = match self.read_system_parameter(serial).await {
+            Ok(params) if params.baud_rate == baud_rate => return Ok(()),
+            Ok(params) => Error::WrongBaudRate {
+                expected: baud_rate,
+                actual: params.baud_rate,
+            },
+            Err(e) => e,
+        };
+
+        // Fall back to the old rate, and make sure we can still talk
+        serial.set_baud_rate(old).await.map_err(Error::Wire)?;
+        self.read_system_parameter(serial).await?;
+        Err(err)
+    }
+}
 
-            let len = u16::from_wire(serial, Some(&mut cksm)).await?;
+//////////////////////////////////////////////////////////////////////////////
+// Packet length
+//////////////////////////////////////////////////////////////////////////////
 
-            if len < 2 {
-                return Err(Error::IncorrectData);
+impl<D: DelaySource> R503<D> {
+    /// Change the module's data packet length, then read it back and split
+    /// data sent from now on to match. Returns the packet length in use.
+    ///
+    /// The handle keeps the old length until ReadSysPara reports the new one,
+    /// as the module may only apply it after a power cycle, see
+    /// [`SystemParameter`]. In that case call [`Self::sync_packet_length()`]
+    /// once it has restarted.
+    pub async fn reconfigure_packet_length<S>(
+        &mut self,
+        serial: &mut S,
+        packet_length: PacketLength,
+    ) -> Result<PacketLength, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.set_system_parameter(serial, SystemParameter::PacketLength(packet_length))
+            .await?;
+        self.sync_packet_length(serial).await
+    }
+
+    /// Read the module's configured data packet length, and use it from now
+    /// on. Returns the packet length read.
+    pub async fn sync_packet_length<S>(&mut self, serial: &mut S) -> Result<PacketLength, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.packet_length = self.read_system_parameter(serial).await?.packet_length;
+        Ok(self.packet_length)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Power on
+//////////////////////////////////////////////////////////////////////////////
+
+impl<D: DelaySource> R503<D> {
+    /// Wait up to [`READY_TIMEOUT_MS`] for the ready byte the module sends
+    /// after power on. Commands sent before that are lost.
+    ///
+    /// Without a delay source, this waits forever for a module that doesn't
+    /// send it.
+    pub async fn wait_ready<S: Read + ErrorType>(&self, serial: &mut S) -> Result<(), Error<S>> {
+        let fut = async {
+            let mut skipped = 0;
+            let mut byte = [0u8; 1];
+            loop {
+                read_exact(serial, &mut byte).await?;
+                if byte[0] == READY {
+                    return Ok(skipped);
+                }
+                skipped += 1;
             }
-            let len_img = (len - 2) as usize;
-            if window.len() < len_img {
-                // TODO better error
-                return Err(Error::IncorrectData);
+        };
+        let skipped = with_timeout(&self.delay, READY_TIMEOUT_MS, fut).await?;
+        self.skipped.fetch_add(skipped, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Switch the module on, and wait until it is ready.
+    ///
+    /// If the module was switched off less than [`power::MIN_OFF_TIME_MS`] ago, this
+    /// first waits out the rest of that time, or returns
+    /// [`PowerError::TooSoon`] without a delay source.
+    pub async fn power_on<S, P, C>(
+        &self,
+        serial: &mut S,
+        power: &mut PowerControl<P, C>,
+    ) -> Result<(), PowerError<S, P::Error>>
+    where
+        S: Read + ErrorType,
+        P: OutputPin,
+        C: Clock,
+    {
+        if power.is_on() {
+            return Ok(());
+        }
+        let remaining_ms = power.off_remaining_ms();
+        if remaining_ms != 0 {
+            if !self.delay.is_enabled() {
+                return Err(PowerError::TooSoon { remaining_ms });
             }
-            let (now, later) = window.split_at_mut(len_img);
-            window = later;
-            match serial.read_exact(now).await {
-                Ok(()) => {}
-                Err(ReadExactError::UnexpectedEof) => return Err(Error::EndOfFile),
-                Err(ReadExactError::Other(w)) => return Err(Error::Wire(w)),
-            };
-            cksm.update(now);
+            self.delay.clone().delay_ms(remaining_ms).await;
+        }
+        power.switch_on().map_err(PowerError::Pin)?;
+        self.wait_ready(serial).await?;
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Locked R503
+//////////////////////////////////////////////////////////////////////////////
 
-            let calc_cksm = cksm.finalize();
-            let rept_cksm = u16::from_wire(serial, None).await?;
+/// A handle to a module whose password has not been verified yet.
+///
+/// If the module's password was changed from the default, it answers every
+/// command with [`ConfirmationCode::MustVerifyPassword`] until VfyPwd
+/// succeeds. This handle only allows that one command, and turns into a full
+/// [`R503`] once the module accepts the password.
+pub struct LockedR503<D: DelaySource = NoTimeout> {
+    inner: R503<D>,
+}
 
-            if calc_cksm != rept_cksm {
-                return Err(Error::BadChecksum);
-            }
+impl LockedR503 {
+    pub fn new_with_address(addr: u32) -> Self {
+        Self {
+            inner: R503::new_with_address(addr),
         }
-        let used = ttl_len - window.len();
-        Ok(used)
+    }
+}
+
+impl<D: DelaySource> LockedR503<D> {
+    /// Time out commands using `delay`, see [`R503::with_delay()`]
+    pub fn with_delay<D2: DelaySource>(self, delay: D2) -> LockedR503<D2> {
+        LockedR503 {
+            inner: self.inner.with_delay(delay),
+        }
+    }
+
+    /// Retry commands after transient failures, see [`R503::with_retry()`]
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        Self {
+            inner: self.inner.with_retry(retry),
+        }
+    }
+
+    /// See [`R503::set_packet_length()`]
+    pub fn set_packet_length(&mut self, packet_length: PacketLength) {
+        self.inner.set_packet_length(packet_length);
+    }
+
+    pub fn address(&self) -> u32 {
+        self.inner.address()
+    }
+
+    /// Verify the password, turning into an unlocked handle with the same
+    /// settings on success. On failure the locked handle is handed back with
+    /// the error, to try again.
+    pub async fn unlock<S>(self, serial: &mut S, password: Password) -> Result<R503<D>, (Self, Error<S>)>
+    where
+        S: Read + Write + ErrorType,
+    {
+        match self.inner.verify_password(serial, password).await {
+            Ok(()) => Ok(self.inner),
+            Err(e) => Err((self, e)),
+        }
+    }
+}
+
+impl<D: DelaySource> Debug for LockedR503<D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LockedR503")
+            .field("address", &self.inner.address)
+            .finish_non_exhaustive()
     }
 }
 
 // Helper macro for implementing basic Command + Acknowledge patterns.
 //
 // Items can optionally take send or receive payloads, though they need to
-// be "owned" items, so not good for streaming.
+// be "owned" items, so not good for streaming. The timeout is in
+// milliseconds, and only applies with a delay source. Retry is the command's
+// `Idempotency`.
 macro_rules! cmds_with_ack {
     (
-        | Function      | Code          | CmdDataTy     | RespDataTy    |
-        | $(-)*         | $(-)*         | $(-)*         | $(-)*         |
-     $( | $func:ident   | $code:ident   | $($cdt:ty)?   | $($rdy:ty)?   | )*
+        | Function      | Code          | Timeout           | Retry         | CmdDataTy     | RespDataTy    |
+        | $(-)*         | $(-)*         | $(-)*             | $(-)*         | $(-)*         | $(-)*         |
+     $( | $func:ident   | $code:ident   | $timeout:literal  | $retry:ident  | $($cdt:ty)?   | $($rdy:ty)?   | )*
     ) => {
         $(
             #[allow(unused_parens)]
@@ -273,79 +1153,239 @@ macro_rules! cmds_with_ack {
             where
                 S: Read + Write + ErrorType,
             {
-                // Send the command
-                //
-                let cmd = Command {
-                    address: self.address,
-                    instruction: Commands::$code,
-                    body: {
-                        let _body = ();
-                        $(
-                            let _body: $cdt = arg;
-                        )?
-                        _body
-                    },
+                let body = {
+                    let _body = ();
+                    $(
+                        let _body: $cdt = arg;
+                    )?
+                    _body
                 };
-                cmd.to_wire(serial).await?;
-
-                // Receive the data
-                // TODO: Timeout?
-                let resp = Response::<($($rdy)?)>::from_wire(serial).await?;
-
-                let mut good = true;
-                good &= resp.address == self.address;
-                good &= resp.ident == PackageIdentifier::AcknowledgePacket.into();
-                if !good {
-                    return Err(Error::IncorrectData);
-                }
-                if resp.confirmation != ConfirmationCode::SuccessCode {
-                    return Err(Error::BadConfirmation(resp.confirmation));
-                }
-                Ok(resp.body)
+                self.command_with_ack(serial, Commands::$code, $timeout, Idempotency::$retry, body)
+                    .await
             }
         )*
     };
 }
 
+/// A CharBuffer and a library slot, for LoadChar and Store
 #[derive(Debug)]
-pub struct LoadCharRequest {
+pub struct BufferSlotRequest {
     pub char_buffer: CharBufferId,
-    pub model_id: u16,
+    pub model_id: ModelId,
 }
 
-impl ToWire for LoadCharRequest {
+impl ToWire for BufferSlotRequest {
     fn size_on_wire(&self) -> usize {
         3
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
-        let [hi, lo] = self.model_id.to_be_bytes();
-        let data = [self.char_buffer.into(), hi, lo];
-        if let Some(c) = cksm {
-            c.update(&data);
+    fn to_wire(&self, out: &mut [u8]) {
+        let rest = put(out, &self.char_buffer);
+        put(rest, &self.model_id);
+    }
+}
+
+/// Loads the template at `model_id` into `char_buffer`
+pub type LoadCharRequest = BufferSlotRequest;
+/// Stores the template in `char_buffer` at `model_id`
+pub type StoreRequest = BufferSlotRequest;
+
+/// Deletes `count` templates, starting at `start_id`
+#[derive(Debug)]
+pub struct DeleteCharRequest {
+    pub start_id: ModelId,
+    pub count: u16,
+}
+
+impl ToWire for DeleteCharRequest {
+    fn size_on_wire(&self) -> usize {
+        4
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        let rest = put(out, &self.start_id);
+        put(rest, &self.count);
+    }
+}
+
+/// Searches `count` library slots, starting at `start_id`, for the
+/// template held in `char_buffer`
+#[derive(Debug)]
+pub struct SearchRequest {
+    pub char_buffer: CharBufferId,
+    pub start_id: ModelId,
+    pub count: u16,
+}
+
+impl ToWire for SearchRequest {
+    fn size_on_wire(&self) -> usize {
+        5
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        let rest = put(out, &self.char_buffer);
+        let rest = put(rest, &self.start_id);
+        put(rest, &self.count);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MatchResponse {
+    pub score: u16,
+}
+
+impl FromWire for MatchResponse {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let score = u16::from_wire(buf)?;
+        Ok(Self { score })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SearchResponse {
+    pub model_id: ModelId,
+    pub score: u16,
+}
+
+impl FromWire for SearchResponse {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        Ok(Self {
+            model_id: ModelId::from_wire(buf)?,
+            score: u16::from_wire(buf)?,
+        })
+    }
+}
+
+/// Basic configuration of the module, as returned by ReadSysPara
+#[derive(Debug, PartialEq)]
+pub struct SystemParameters {
+    pub status: StatusRegister,
+    /// Fixed value: 0x0009
+    pub system_id: u16,
+    /// Number of templates the library can hold
+    pub library_size: u16,
+    pub security_level: SecurityLevel,
+    pub address: u32,
+    pub packet_length: PacketLength,
+    pub baud_rate: BaudRate,
+}
+
+impl SystemParameters {
+    /// `id`, if this module's library has a slot for it
+    pub fn model_id(&self, id: u16) -> Option<ModelId> {
+        ModelId::new(id, self.library_size)
+    }
+}
+
+impl FromWire for SystemParameters {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let bytes = <[u8; 16]>::from_wire(buf)?;
+        let word = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        // The enum-ish fields are all sent as u16, but only ever use the low byte
+        fn small<T: TryFrom<u8>>(word: u16, kind: &'static str) -> Result<T, FrameError> {
+            u8::try_from(word)
+                .ok()
+                .and_then(|b| T::try_from(b).ok())
+                .ok_or(FrameError::UnknownValue {
+                    kind,
+                    value: word.into(),
+                })
         }
-        serial.write_all(&data).await.map_err(Error::Wire)
+
+        let security_level = small(word(6), "SecurityLevel")?;
+        let packet_length = small(word(12), "PacketLength")?;
+        let baud_rate = small(word(14), "BaudRate")?;
+
+        Ok(Self {
+            status: StatusRegister::from_bits(word(0)),
+            system_id: word(2),
+            library_size: word(4),
+            security_level,
+            address: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            packet_length,
+            baud_rate,
+        })
     }
 }
 
-impl R503 {
-    cmds_with_ack! {
-        | Function              | Code                      | CmdDataTy             | RespDataTy    |
-        | --------              | ----                      | ---------             | ----------    |
-        | get_rand_code         | GetRandomCode             |                       | u32           |
-        | read_system_parameter | ReadSystemParameter       |                       | [u8; 16]      |
-        | get_image             | GetImage                  |                       |               |
-        | upload_image          | UpImage                   |                       |               |
-        | generate_char         | GenChar                   | CharBufferId          |               |
-        | generate_template     | RegModel                  |                       |               |
-        | upload_template       | UpChar                    | CharBufferId          |               |
-        | set_aura              | AuraControl               | AuraControlPayload    |               |
-        | read_idx_table        | ReadIndexTable            | IndexTableIdx         | [u8; 32]      |
-        | empty                 | Empty                     |                       |               |
-        | load_char             | LoadChar                  | LoadCharRequest       |               |
+/// Product information, as returned by ReadProdInfo
+#[derive(Debug, PartialEq)]
+pub struct ProductInfo {
+    pub module_model: String<16>,
+    pub batch_number: String<4>,
+    pub serial_number: String<8>,
+    /// Main version, then sub-version
+    pub hardware_version: [u8; 2],
+    pub sensor_model: String<8>,
+    pub sensor_width: u16,
+    pub sensor_height: u16,
+    /// Size of a single template, in bytes
+    pub template_size: u16,
+    /// Number of templates the library can hold
+    pub library_size: u16,
+}
+
+impl FromWire for ProductInfo {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        Ok(Self {
+            module_model: String::from_wire(buf)?,
+            batch_number: String::from_wire(buf)?,
+            serial_number: String::from_wire(buf)?,
+            hardware_version: <[u8; 2]>::from_wire(buf)?,
+            sensor_model: String::from_wire(buf)?,
+            sensor_width: u16::from_wire(buf)?,
+            sensor_height: u16::from_wire(buf)?,
+            template_size: u16::from_wire(buf)?,
+            library_size: u16::from_wire(buf)?,
+        })
     }
 }
+
+/// Firmware or algorithm library version string
+pub type VersionString = String<32>;
+
+// All the basic Command + Acknowledge commands, as a table for `$mac`.
+//
+// Used for both the async methods below, and the blocking ones in
+// [`blocking`], so the two always offer the same commands.
+macro_rules! command_table {
+    ($mac:ident) => {
+        $mac! {
+            | Function              | Code                | Timeout | Retry         | CmdDataTy           | RespDataTy              |
+            | --------              | ----                | ------- | -----         | ---------           | ----------              |
+            | get_rand_code         | GetRandomCode       | 1000    | Idempotent    |                     | u32                     |
+            | read_system_parameter | ReadSystemParameter | 1000    | Idempotent    |                     | SystemParameters        |
+            | get_image             | GetImage            | 2000    | Idempotent    |                     |                         |
+            | upload_image          | UpImage             | 1000    | NotIdempotent |                     |                         |
+            | generate_char         | GenChar             | 2000    | Idempotent    | CharBufferId        |                         |
+            | generate_template     | RegModel            | 2000    | NotIdempotent |                     |                         |
+            | upload_template       | UpChar              | 1000    | NotIdempotent | CharBufferId        |                         |
+            | set_aura              | AuraControl         | 1000    | Idempotent    | AuraControlPayload  |                         |
+            | read_idx_table        | ReadIndexTable      | 1000    | Idempotent    | IndexTableIdx       | [u8; 32]                |
+            | empty                 | Empty               | 3000    | NotIdempotent |                     |                         |
+            | load_char             | LoadChar            | 1000    | Idempotent    | LoadCharRequest     |                         |
+            | store                 | Store               | 2000    | NotIdempotent | StoreRequest        |                         |
+            | delete_char           | DeleteChar          | 2000    | NotIdempotent | DeleteCharRequest   |                         |
+            | match_templates       | Match               | 1000    | Idempotent    |                     | MatchResponse           |
+            | search                | Search              | 3000    | Idempotent    | SearchRequest       | SearchResponse          |
+            | template_count        | TempleteNum         | 1000    | Idempotent    |                     | u16                     |
+            | verify_password       | VfyPwd              | 1000    | Idempotent    | Password            |                         |
+            | set_password          | SetPwd              | 2000    | NotIdempotent | Password            |                         |
+            | set_system_parameter  | SetSysPara          | 2000    | NotIdempotent | SystemParameter     |                         |
+            | write_notepad         | WriteNotepad        | 2000    | NotIdempotent | WriteNotepadRequest |                         |
+            | read_notepad          | ReadNotepad         | 1000    | Idempotent    | NotepadPage         | [u8; NOTEPAD_PAGE_SIZE] |
+            | read_product_info     | ReadProdInfo        | 1000    | Idempotent    |                     | ProductInfo             |
+            | get_firmware_version  | GetFwVer            | 1000    | Idempotent    |                     | VersionString           |
+            | get_algorithm_version | GetAlgVer           | 1000    | Idempotent    |                     | VersionString           |
+            | handshake             | HandShake           | 1000    | Idempotent    |                     |                         |
+            | check_sensor          | CheckSensor         | 1000    | Idempotent    |                     |                         |
+            | soft_reset            | SoftRst             | 1000    | NotIdempotent |                     |                         |
+            | get_image_ex          | GetImageEx          | 2000    | Idempotent    |                     |                         |
+        }
+    };
+}
+pub(crate) use command_table;
+
+impl<D: DelaySource> R503<D> {
+    command_table!(cmds_with_ack);
+}
@@ -0,0 +1,377 @@
+use core::fmt::{Debug, Display};
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::{
+    timeout::DelaySource,
+    wire_traits::{put, ToWire},
+    Checksum, Error, R503,
+};
+
+/// Number of notepad pages
+pub const NOTEPAD_PAGES: u8 = 16;
+/// Size of a single notepad page, in bytes
+pub const NOTEPAD_PAGE_SIZE: usize = 32;
+
+//////////////////////////////////////////////////////////////////////////////
+// Raw notepad access
+//////////////////////////////////////////////////////////////////////////////
+
+/// A notepad page number, 0..16
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NotepadPage {
+    val: u8,
+}
+
+impl NotepadPage {
+    pub fn new(page: u8) -> Option<Self> {
+        if page < NOTEPAD_PAGES {
+            Some(Self { val: page })
+        } else {
+            None
+        }
+    }
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..NOTEPAD_PAGES).map(|val| Self { val })
+    }
+
+    pub fn number(&self) -> u8 {
+        self.val
+    }
+}
+
+impl ToWire for NotepadPage {
+    fn size_on_wire(&self) -> usize {
+        1
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        self.val.to_wire(out)
+    }
+}
+
+/// Overwrites a whole notepad page
+#[derive(Debug)]
+pub struct WriteNotepadRequest {
+    pub page: NotepadPage,
+    pub data: [u8; NOTEPAD_PAGE_SIZE],
+}
+
+impl ToWire for WriteNotepadRequest {
+    fn size_on_wire(&self) -> usize {
+        1 + NOTEPAD_PAGE_SIZE
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        let out = put(out, &self.page);
+        put(out, &self.data);
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Record Store
+//////////////////////////////////////////////////////////////////////////////
+
+// Page layout:
+//
+// | 0     | 1     | 2..4        | 4   | 5   | 6..30 | 30..32   |
+// | magic | state | write count | key | len | value | checksum |
+//
+// The write count is kept when a record is removed, so that new records can
+// be placed on the least worn page. The checksum covers bytes 0..30.
+const MAGIC: u8 = 0x52;
+const STATE_FREE: u8 = 0x00;
+const STATE_USED: u8 = 0x01;
+
+/// Largest value a single record can hold, in bytes
+pub const MAX_RECORD_LEN: usize = 24;
+
+/// A single key/value record, stored in one notepad page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    key: u8,
+    len: u8,
+    data: [u8; MAX_RECORD_LEN],
+}
+
+impl Record {
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+/// What we found in a notepad page when it was last read or written
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageState {
+    /// Never written by the store, or a removed record
+    Free { writes: u16 },
+    Used { writes: u16, record: Record },
+    /// Checksum mismatch, or data not written by the store
+    Corrupt,
+}
+
+pub enum StoreError<S: ErrorType> {
+    Device(Error<S>),
+    /// Every page holds a record, or data the store doesn't recognize
+    Full,
+    /// The value is longer than [`MAX_RECORD_LEN`]
+    TooLong,
+}
+
+impl<S: ErrorType> From<Error<S>> for StoreError<S> {
+    fn from(value: Error<S>) -> Self {
+        StoreError::Device(value)
+    }
+}
+
+impl<S> Debug for StoreError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StoreError::Device(e) => {
+                f.write_str("StoreError::Device(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            StoreError::Full => f.write_str("StoreError::Full"),
+            StoreError::TooLong => f.write_str("StoreError::TooLong"),
+        }
+    }
+}
+
+impl<S> Display for StoreError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StoreError::Device(e) => write!(f, "{e}"),
+            StoreError::Full => f.write_str("no free notepad page"),
+            StoreError::TooLong => write!(f, "record longer than {MAX_RECORD_LEN} bytes"),
+        }
+    }
+}
+
+impl<S> core::error::Error for StoreError<S>
+where
+    S: ErrorType,
+    S::Error: Debug,
+{
+}
+
+/// A small key/value store on top of the module's notepad.
+///
+/// Each record takes one page, so the store holds up to 16 records of up to
+/// [`MAX_RECORD_LEN`] bytes. Useful for metadata that should live next to the
+/// templates, like a schema version or a mapping from template id to user id.
+///
+/// Every page carries a checksum, pages that fail it are reported by
+/// [`Self::corrupt_pages()`] and are never returned as records or
+/// overwritten, so pages written through [`R503::write_notepad()`] survive. To reduce
+/// flash wear, unchanged values are not rewritten, and new records go to the
+/// free page that has been written the fewest times.
+pub struct RecordStore {
+    pages: [PageState; NOTEPAD_PAGES as usize],
+}
+
+impl RecordStore {
+    /// Read and check every notepad page
+    pub async fn load<S, D: DelaySource>(r5: &R503<D>, serial: &mut S) -> Result<Self, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let mut pages = [const { PageState::Corrupt }; NOTEPAD_PAGES as usize];
+        for (page, state) in NotepadPage::all().zip(pages.iter_mut()) {
+            let data = r5.read_notepad(serial, page).await?;
+            *state = decode(&data);
+        }
+        Ok(Self { pages })
+    }
+
+    pub fn get(&self, key: u8) -> Option<&Record> {
+        self.records().find(|r| r.key == key)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.pages.iter().filter_map(|p| match p {
+            PageState::Used { record, .. } => Some(record),
+            _ => None,
+        })
+    }
+
+    pub fn page_states(&self) -> &[PageState] {
+        &self.pages
+    }
+
+    pub fn corrupt_pages(&self) -> impl Iterator<Item = NotepadPage> + '_ {
+        NotepadPage::all()
+            .zip(self.pages.iter())
+            .filter(|(_, s)| **s == PageState::Corrupt)
+            .map(|(p, _)| p)
+    }
+
+    /// Insert or update a record
+    pub async fn put<S, D: DelaySource>(
+        &mut self,
+        r5: &R503<D>,
+        serial: &mut S,
+        key: u8,
+        value: &[u8],
+    ) -> Result<(), StoreError<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        if value.len() > MAX_RECORD_LEN {
+            return Err(StoreError::TooLong);
+        }
+        let existing = self.pages.iter().position(|p| match p {
+            PageState::Used { record, .. } => record.key == key,
+            _ => false,
+        });
+        let idx = match existing {
+            Some(idx) => idx,
+            None => self.least_worn_free().ok_or(StoreError::Full)?,
+        };
+
+        let writes = match &self.pages[idx] {
+            PageState::Used { record, .. } if record.value() == value => return Ok(()),
+            PageState::Used { writes, .. } | PageState::Free { writes } => *writes,
+            PageState::Corrupt => 0,
+        };
+        let mut data = [0u8; MAX_RECORD_LEN];
+        data[..value.len()].copy_from_slice(value);
+        let state = PageState::Used {
+            writes: writes.wrapping_add(1),
+            record: Record {
+                key,
+                len: value.len() as u8,
+                data,
+            },
+        };
+        self.write_page(r5, serial, idx, state).await?;
+        Ok(())
+    }
+
+    /// Remove a record, returns whether it existed
+    pub async fn remove<S, D: DelaySource>(
+        &mut self,
+        r5: &R503<D>,
+        serial: &mut S,
+        key: u8,
+    ) -> Result<bool, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let found = self.pages.iter().enumerate().find_map(|(i, p)| match p {
+            PageState::Used { record, writes } if record.key == key => Some((i, *writes)),
+            _ => None,
+        });
+        let Some((idx, writes)) = found else {
+            return Ok(false);
+        };
+        let state = PageState::Free {
+            writes: writes.wrapping_add(1),
+        };
+        self.write_page(r5, serial, idx, state).await?;
+        Ok(true)
+    }
+
+    /// The least worn free page. Corrupt pages are never reused, they may
+    /// hold data written with [`R503::write_notepad()`].
+    fn least_worn_free(&self) -> Option<usize> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| match p {
+                PageState::Free { writes } => Some((i, *writes)),
+                _ => None,
+            })
+            .min_by_key(|(_, w)| *w)
+            .map(|(i, _)| i)
+    }
+
+    async fn write_page<S, D: DelaySource>(
+        &mut self,
+        r5: &R503<D>,
+        serial: &mut S,
+        idx: usize,
+        state: PageState,
+    ) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let req = WriteNotepadRequest {
+            page: NotepadPage { val: idx as u8 },
+            data: encode(&state),
+        };
+        r5.write_notepad(serial, req).await?;
+        self.pages[idx] = state;
+        Ok(())
+    }
+}
+
+fn page_checksum(data: &[u8; NOTEPAD_PAGE_SIZE]) -> [u8; 2] {
+    let mut cksm = Checksum::new();
+    cksm.update(&data[..NOTEPAD_PAGE_SIZE - 2]);
+    cksm.finalize().to_be_bytes()
+}
+
+fn encode(state: &PageState) -> [u8; NOTEPAD_PAGE_SIZE] {
+    let mut out = [0u8; NOTEPAD_PAGE_SIZE];
+    out[0] = MAGIC;
+    match state {
+        PageState::Free { writes } => {
+            out[1] = STATE_FREE;
+            out[2..4].copy_from_slice(&writes.to_be_bytes());
+        }
+        PageState::Used { writes, record } => {
+            out[1] = STATE_USED;
+            out[2..4].copy_from_slice(&writes.to_be_bytes());
+            out[4] = record.key;
+            out[5] = record.len;
+            out[6..30].copy_from_slice(&record.data);
+        }
+        // Never written, but a zeroed page reads back as free
+        PageState::Corrupt => return [0u8; NOTEPAD_PAGE_SIZE],
+    }
+    let cksm = page_checksum(&out);
+    out[30..].copy_from_slice(&cksm);
+    out
+}
+
+fn decode(data: &[u8; NOTEPAD_PAGE_SIZE]) -> PageState {
+    // Blank pages, as shipped from the factory
+    if data.iter().all(|b| *b == 0x00) || data.iter().all(|b| *b == 0xFF) {
+        return PageState::Free { writes: 0 };
+    }
+    if data[0] != MAGIC || data[30..] != page_checksum(data) {
+        return PageState::Corrupt;
+    }
+    let writes = u16::from_be_bytes([data[2], data[3]]);
+    match data[1] {
+        STATE_FREE => PageState::Free { writes },
+        STATE_USED if data[5] as usize <= MAX_RECORD_LEN => {
+            let mut rec = [0u8; MAX_RECORD_LEN];
+            rec.copy_from_slice(&data[6..30]);
+            PageState::Used {
+                writes,
+                record: Record {
+                    key: data[4],
+                    len: data[5],
+                    data: rec,
+                },
+            }
+        }
+        _ => PageState::Corrupt,
+    }
+}
@@ -0,0 +1,174 @@
+use core::fmt::{Debug, Display};
+
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::ErrorType;
+
+use crate::Error;
+
+/// "After the power is turned off, the power must be turned on at least two
+/// seconds later."
+pub const MIN_OFF_TIME_MS: u32 = 2_000;
+/// Time to wait for the ready byte after power on.
+///
+/// The manual gives about 50ms for initialization.
+pub const READY_TIMEOUT_MS: u32 = 500;
+
+/// A millisecond clock, used to tell how long the module has been off.
+///
+/// Implemented for closures, e.g. `|| embassy_time::Instant::now().as_millis()`.
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now_ms(&self) -> u64 {
+        self()
+    }
+}
+
+/// Which pin level switches the module's main supply on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polarity {
+    ActiveHigh,
+    /// e.g. the gate of a P-channel high side switch
+    ActiveLow,
+}
+
+/// Switches the module's main 3.3V supply through a GPIO.
+///
+/// Keeps track of when the module was switched off, so that it is never
+/// switched on again before [`MIN_OFF_TIME_MS`] has passed. The touch supply
+/// (3.3VT) should stay on, so finger detection keeps working while the module
+/// is off. Use [`crate::R503::power_on()`] to switch the module on.
+pub struct PowerControl<P: OutputPin, C: Clock> {
+    pin: P,
+    polarity: Polarity,
+    clock: C,
+    /// When the module was switched off, `None` while it is on
+    off_since: Option<u64>,
+}
+
+impl<P: OutputPin, C: Clock> PowerControl<P, C> {
+    /// Take control of `pin`, and switch the module off.
+    ///
+    /// As the module may have been on until just now, the full off time
+    /// applies before it can be switched on.
+    pub fn new(pin: P, polarity: Polarity, clock: C) -> Result<Self, P::Error> {
+        let mut pc = Self {
+            pin,
+            polarity,
+            clock,
+            off_since: None,
+        };
+        pc.power_off()?;
+        Ok(pc)
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.off_since.is_none()
+    }
+
+    /// Time left before the module may be switched on again, in milliseconds
+    pub fn off_remaining_ms(&self) -> u32 {
+        let Some(since) = self.off_since else {
+            return 0;
+        };
+        let elapsed = self.clock.now_ms().saturating_sub(since);
+        u64::from(MIN_OFF_TIME_MS).saturating_sub(elapsed) as u32
+    }
+
+    /// Switch the module off. Does nothing if it is already off.
+    pub fn power_off(&mut self) -> Result<(), P::Error> {
+        if self.off_since.is_some() {
+            return Ok(());
+        }
+        match self.polarity {
+            Polarity::ActiveHigh => self.pin.set_low()?,
+            Polarity::ActiveLow => self.pin.set_high()?,
+        }
+        self.off_since = Some(self.clock.now_ms());
+        Ok(())
+    }
+
+    /// Switch the module on, without checking the off time
+    pub(crate) fn switch_on(&mut self) -> Result<(), P::Error> {
+        match self.polarity {
+            Polarity::ActiveHigh => self.pin.set_high()?,
+            Polarity::ActiveLow => self.pin.set_low()?,
+        }
+        self.off_since = None;
+        Ok(())
+    }
+
+    /// Give the pin back
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+pub enum PowerError<S: ErrorType, E> {
+    Device(Error<S>),
+    Pin(E),
+    /// The module was switched off less than [`MIN_OFF_TIME_MS`] ago, and
+    /// there's no delay source to wait out the rest
+    TooSoon { remaining_ms: u32 },
+}
+
+impl<S: ErrorType, E> From<Error<S>> for PowerError<S, E> {
+    fn from(value: Error<S>) -> Self {
+        PowerError::Device(value)
+    }
+}
+
+impl<S, E> Debug for PowerError<S, E>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PowerError::Device(e) => {
+                f.write_str("PowerError::Device(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            PowerError::Pin(e) => {
+                f.write_str("PowerError::Pin(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            PowerError::TooSoon { remaining_ms } => f
+                .debug_struct("PowerError::TooSoon")
+                .field("remaining_ms", remaining_ms)
+                .finish(),
+        }
+    }
+}
+
+impl<S, E> Display for PowerError<S, E>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PowerError::Device(e) => write!(f, "{e}"),
+            PowerError::Pin(e) => write!(f, "power pin error: {e:?}"),
+            PowerError::TooSoon { remaining_ms } => {
+                write!(f, "module switched off too recently, {remaining_ms}ms to go")
+            }
+        }
+    }
+}
+
+impl<S, E> core::error::Error for PowerError<S, E>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    E: Debug,
+{
+}
@@ -0,0 +1,70 @@
+use embedded_io_async::ErrorType;
+
+use crate::{constants::ConfirmationCode, Error};
+
+/// Whether a command can safely run twice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Idempotency {
+    /// Running it again has the same effect as running it once, like reads
+    /// and image captures
+    Idempotent,
+    /// Writes flash, overwrites its own inputs, reboots the module, or is
+    /// followed by data packets. Only retried when the module says it never
+    /// got the command.
+    NotIdempotent,
+}
+
+/// How to retry commands after transient failures.
+///
+/// A command is retried after [`ConfirmationCode::ErrorCode`], which means
+/// the module didn't receive it properly and so didn't run it. Idempotent
+/// commands are also retried after [`Error::Timeout`] or a bad checksum on
+/// the reply, where the module may well have run them.
+///
+/// The line is resynced between attempts, see [`crate::R503::resync()`].
+/// Resyncing and backoff need a delay source, without one retries follow
+/// each other right away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total tries, including the first one
+    pub max_attempts: u8,
+    /// Wait before the first retry, doubled for each retry after that
+    pub backoff_ms: u32,
+}
+
+impl RetryPolicy {
+    /// Never retry. This is the default.
+    pub const NONE: Self = Self {
+        max_attempts: 1,
+        backoff_ms: 0,
+    };
+
+    pub fn new(max_attempts: u8, backoff_ms: u32) -> Self {
+        Self {
+            max_attempts,
+            backoff_ms,
+        }
+    }
+
+    /// Whether to try a command again after `err`
+    pub fn should_retry<S: ErrorType>(&self, err: &Error<S>, idempotency: Idempotency) -> bool {
+        match err {
+            Error::BadConfirmation(ConfirmationCode::ErrorCode) => true,
+            // The command may have run, only the reply was lost
+            Error::Timeout | Error::BadChecksum { .. } => idempotency == Idempotency::Idempotent,
+            _ => false,
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from 1
+    pub(crate) fn backoff_for(&self, retry: u8) -> u32 {
+        let shift = u32::from(retry.saturating_sub(1)).min(31);
+        self.backoff_ms.saturating_mul(1 << shift)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
@@ -0,0 +1,78 @@
+//! Host serial port transport, for Linux hosts like the Raspberry Pi wired as
+//! in the README. Needs the `std` feature and a tokio runtime.
+
+use std::{io, time::Duration};
+
+use embedded_io_async::{ErrorType, Read, Write};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits};
+
+use crate::{constants::BaudRate, BaudRateControl};
+
+/// A tty, like `/dev/serial0` or `/dev/ttyUSB0`, driven by tokio
+pub struct TokioSerial {
+    port: SerialStream,
+}
+
+impl TokioSerial {
+    /// Open `path` at `baud_rate`, with the module's fixed 8N1 framing and no
+    /// flow control.
+    ///
+    /// Anything already waiting in the port's buffers is discarded.
+    pub fn open(path: &str, baud_rate: BaudRate) -> io::Result<Self> {
+        let port = tokio_serial::new(path, baud_rate.bits_per_second())
+            .data_bits(DataBits::Eight)
+            .parity(Parity::None)
+            .stop_bits(StopBits::One)
+            .flow_control(FlowControl::None)
+            .open_native_async()?;
+        port.clear(ClearBuffer::All)?;
+        Ok(Self { port })
+    }
+
+    /// Use a port that is already open and configured
+    pub fn new(port: SerialStream) -> Self {
+        Self { port }
+    }
+
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+}
+
+impl ErrorType for TokioSerial {
+    type Error = io::Error;
+}
+
+impl Read for TokioSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.port.read(buf).await
+    }
+}
+
+impl Write for TokioSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.port.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.port.flush().await
+    }
+}
+
+impl BaudRateControl for TokioSerial {
+    async fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<(), Self::Error> {
+        self.port.set_baud_rate(baud_rate.bits_per_second())?;
+        Ok(())
+    }
+}
+
+/// Tokio timers as a delay source, see [`crate::R503::with_delay()`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioDelay;
+
+impl embedded_hal_async::delay::DelayNs for TokioDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        tokio::time::sleep(Duration::from_nanos(ns.into())).await
+    }
+}
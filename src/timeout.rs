@@ -0,0 +1,115 @@
+use core::future::Future;
+
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read};
+
+use crate::Error;
+
+/// Default time to wait for an acknowledgement
+pub const DEFAULT_TIMEOUT_MS: u32 = 1_000;
+/// Time to wait for each data packet of an upload
+pub const DATA_PACKET_TIMEOUT_MS: u32 = 1_000;
+/// Time to wait for each step of AutoEnroll or AutoIdentify.
+///
+/// The module gives up by itself after 10 seconds without a finger.
+pub const AUTO_STEP_TIMEOUT_MS: u32 = 15_000;
+/// Time to wait for the acknowledgement of Cancel, including any step
+/// acknowledgements the cancelled operation sends before it
+pub const CANCEL_TIMEOUT_MS: u32 = 2_000;
+/// How long the line must be silent before it is considered back in sync
+pub const RESYNC_QUIET_MS: u32 = 20;
+/// Give up resyncing after discarding this many bytes
+pub const RESYNC_MAX_BYTES: usize = 1024;
+
+/// A source of async delays, used to time out commands.
+///
+/// Implemented for any [`DelayNs`] that is also `Clone`, like
+/// `embassy_time::Delay`.
+pub trait DelaySource: Clone {
+    /// Wait for `ms` milliseconds
+    fn delay_ms(&mut self, ms: u32) -> impl Future<Output = ()>;
+
+    /// Whether delays ever complete
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+impl<T: DelayNs + Clone> DelaySource for T {
+    async fn delay_ms(&mut self, ms: u32) {
+        DelayNs::delay_ms(self, ms).await
+    }
+}
+
+/// Never times out. This is the default for [`crate::R503`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoTimeout;
+
+impl DelaySource for NoTimeout {
+    async fn delay_ms(&mut self, _ms: u32) {
+        core::future::pending().await
+    }
+
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Run `fut`, giving up with [`Error::Timeout`] after `ms` milliseconds
+pub(crate) async fn with_timeout<D, S, T, F>(delay: &D, ms: u32, fut: F) -> Result<T, Error<S>>
+where
+    D: DelaySource,
+    S: ErrorType,
+    F: Future<Output = Result<T, Error<S>>>,
+{
+    let mut delay = delay.clone();
+    match select(fut, delay.delay_ms(ms)).await {
+        Either::First(res) => res,
+        Either::Second(()) => Err(Error::Timeout),
+    }
+}
+
+/// Discard incoming bytes until the line has been quiet for
+/// [`RESYNC_QUIET_MS`], returning the number of bytes discarded.
+///
+/// Does nothing without a delay source, as there's no way to tell that the
+/// line went quiet.
+pub(crate) async fn drain<D, S>(delay: &D, serial: &mut S) -> Result<usize, Error<S>>
+where
+    D: DelaySource,
+    S: Read + ErrorType,
+{
+    if !delay.is_enabled() {
+        return Ok(0);
+    }
+    let mut buf = [0u8; 32];
+    let mut drained = 0;
+    while drained < RESYNC_MAX_BYTES {
+        let mut delay = delay.clone();
+        match select(serial.read(&mut buf), delay.delay_ms(RESYNC_QUIET_MS)).await {
+            Either::First(Ok(0)) => return Err(Error::EndOfFile),
+            Either::First(Ok(n)) => drained += n,
+            Either::First(Err(e)) => return Err(Error::Wire(e)),
+            Either::Second(()) => break,
+        }
+    }
+    Ok(drained)
+}
+
+/// Pass `res` through, draining the line first if it is a timeout, so the
+/// rest of the late response doesn't get mistaken for the next one.
+pub(crate) async fn resync_on_timeout<D, S, T>(
+    delay: &D,
+    serial: &mut S,
+    res: Result<T, Error<S>>,
+) -> Result<T, Error<S>>
+where
+    D: DelaySource,
+    S: Read + ErrorType,
+{
+    if let Err(Error::Timeout) = res {
+        drain(delay, serial).await?;
+    }
+    res
+}
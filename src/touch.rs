@@ -0,0 +1,172 @@
+use core::{
+    fmt::{Debug, Display},
+    sync::atomic::Ordering,
+};
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::digital::Wait;
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::{
+    auto::{AutoIdentify, AutoIdentifyConfig, AutoIdentifyResponse},
+    power::{Clock, PowerControl, PowerError},
+    timeout::DelaySource,
+    Error, R503,
+};
+
+/// The WAKEUP line (blue wire), which the module pulls low while a finger
+/// touches the sensor.
+///
+/// This only needs the touch supply (3.3VT, white wire), so it keeps working
+/// while the main supply is switched off with [`PowerControl`].
+pub struct TouchSensor<W: Wait> {
+    pin: W,
+}
+
+impl<W: Wait> TouchSensor<W> {
+    pub fn new(pin: W) -> Self {
+        Self { pin }
+    }
+
+    /// Wait until a finger touches the sensor. Returns right away if one
+    /// already does.
+    pub async fn wait_for_finger(&mut self) -> Result<(), W::Error> {
+        self.pin.wait_for_low().await
+    }
+
+    /// Wait until no finger touches the sensor. Returns right away if none
+    /// does.
+    pub async fn wait_for_release(&mut self) -> Result<(), W::Error> {
+        self.pin.wait_for_high().await
+    }
+
+    /// Give the pin back
+    pub fn release(self) -> W {
+        self.pin
+    }
+}
+
+pub enum TouchError<S: ErrorType, TE, PE> {
+    Touch(TE),
+    /// Switching the module on or off failed
+    Power(PowerError<S, PE>),
+    /// The module was on, but identification failed
+    Device(Error<S>),
+}
+
+impl<S: ErrorType, TE, PE> From<PowerError<S, PE>> for TouchError<S, TE, PE> {
+    fn from(value: PowerError<S, PE>) -> Self {
+        TouchError::Power(value)
+    }
+}
+
+impl<S: ErrorType, TE, PE> From<Error<S>> for TouchError<S, TE, PE> {
+    fn from(value: Error<S>) -> Self {
+        TouchError::Device(value)
+    }
+}
+
+impl<S, TE, PE> Debug for TouchError<S, TE, PE>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    TE: Debug,
+    PE: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TouchError::Touch(e) => {
+                f.write_str("TouchError::Touch(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            TouchError::Power(e) => {
+                f.write_str("TouchError::Power(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+            TouchError::Device(e) => {
+                f.write_str("TouchError::Device(")?;
+                f.write_fmt(format_args!("{e:?}"))?;
+                f.write_str(")")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<S, TE, PE> Display for TouchError<S, TE, PE>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    TE: Debug,
+    PE: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TouchError::Touch(e) => write!(f, "touch pin error: {e:?}"),
+            TouchError::Power(e) => write!(f, "{e}"),
+            TouchError::Device(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl<S, TE, PE> core::error::Error for TouchError<S, TE, PE>
+where
+    S: ErrorType,
+    S::Error: Debug,
+    TE: Debug,
+    PE: Debug,
+{
+}
+
+impl<D: DelaySource> R503<D> {
+    /// Sleep until a finger touches the sensor, then power the module on, run
+    /// AutoIdentify and power it off again. Call this in a loop.
+    ///
+    /// A finger still resting on the sensor from the last round has to be
+    /// lifted first, so the same touch isn't identified twice. The module is
+    /// powered off whether or not identification succeeds, so it only draws
+    /// current while a finger is being identified.
+    pub async fn identify_on_touch<S, W, P, C>(
+        &self,
+        serial: &mut S,
+        touch: &mut TouchSensor<W>,
+        power: &mut PowerControl<P, C>,
+        cfg: AutoIdentifyConfig,
+    ) -> Result<AutoIdentifyResponse, TouchError<S, W::Error, P::Error>>
+    where
+        S: Read + Write + ErrorType,
+        W: Wait,
+        P: OutputPin,
+        C: Clock,
+    {
+        touch.wait_for_release().await.map_err(TouchError::Touch)?;
+        touch.wait_for_finger().await.map_err(TouchError::Touch)?;
+
+        let res = self.identify_powered(serial, power, cfg).await;
+        power.power_off().map_err(|e| TouchError::Power(PowerError::Pin(e)))?;
+        res
+    }
+
+    async fn identify_powered<S, TE, P, C>(
+        &self,
+        serial: &mut S,
+        power: &mut PowerControl<P, C>,
+        cfg: AutoIdentifyConfig,
+    ) -> Result<AutoIdentifyResponse, TouchError<S, TE, P::Error>>
+    where
+        S: Read + Write + ErrorType,
+        P: OutputPin,
+        C: Clock,
+    {
+        self.power_on(serial, power).await?;
+        let mut identify = AutoIdentify::new(self.address, serial).with_delay(self.delay.clone());
+        identify.start(cfg).await?;
+        let res = identify.wait_auto().await;
+        self.skipped.fetch_add(identify.take_skipped_bytes(), Ordering::Relaxed);
+        Ok(res?)
+    }
+}
@@ -0,0 +1,90 @@
+use core::ops::RangeInclusive;
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::{
+    constants::{CharBufferId, ConfirmationCode, ModelId},
+    enroll::POLL_INTERVAL_MS,
+    timeout::DelaySource,
+    Error, LoadCharRequest, SearchRequest, R503,
+};
+
+/// A finger that matched a stored template
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchResult {
+    pub model_id: ModelId,
+    /// Higher is a closer match. Compare it against your own threshold for
+    /// a stricter check than the module's security level.
+    pub score: u16,
+}
+
+impl<D: DelaySource> R503<D> {
+    /// Check a finger against the template at `model_id`, 1:1.
+    ///
+    /// Waits for a finger, extracts its features into CharBuffer 1, loads the
+    /// template into CharBuffer 2 and compares the two. Returns `None` if they
+    /// don't match. Unlike AutoIdentify, the LED is left alone and there is
+    /// no timeout, drop the future to give up.
+    pub async fn verify<S>(&self, serial: &mut S, model_id: ModelId) -> Result<Option<MatchResult>, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.capture(serial).await?;
+        let req = LoadCharRequest {
+            char_buffer: CharBufferId::Two,
+            model_id,
+        };
+        self.load_char(serial, req).await?;
+        match self.match_templates(serial).await {
+            Ok(res) => Ok(Some(MatchResult {
+                model_id,
+                score: res.score,
+            })),
+            Err(Error::BadConfirmation(ConfirmationCode::FailFingerDoesntMatch)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Search the templates with ids in `range`, inclusive, for a finger, 1:N.
+    ///
+    /// Waits for a finger, extracts its features into CharBuffer 1 and
+    /// searches the range, so a device shared by several tenants can look in
+    /// one tenant's slots only. Returns `None` if no template matches. An
+    /// empty range returns `None` without waiting for a finger.
+    pub async fn identify<S>(
+        &self,
+        serial: &mut S,
+        range: RangeInclusive<ModelId>,
+    ) -> Result<Option<MatchResult>, Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        let (first, last) = (range.start().get(), range.end().get());
+        if first > last {
+            return Ok(None);
+        }
+        self.capture(serial).await?;
+        let req = SearchRequest {
+            char_buffer: CharBufferId::One,
+            start_id: *range.start(),
+            count: last - first + 1,
+        };
+        match self.search(serial, req).await {
+            Ok(res) => Ok(Some(MatchResult {
+                model_id: res.model_id,
+                score: res.score,
+            })),
+            Err(Error::BadConfirmation(ConfirmationCode::FailToFindMatchingFinger)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wait for a finger and extract its features into CharBuffer 1
+    async fn capture<S>(&self, serial: &mut S) -> Result<(), Error<S>>
+    where
+        S: Read + Write + ErrorType,
+    {
+        self.wait_for_finger(serial, POLL_INTERVAL_MS).await?;
+        self.generate_char(serial, CharBufferId::One).await
+    }
+}
@@ -1,31 +1,63 @@
-use embedded_io_async::{ErrorType, Read, ReadExactError, Write};
-
-use crate::{Checksum, Error};
+use crate::frame::FrameError;
 
 /// Serialize the data TO the wire.
 pub trait ToWire {
     /// Size on the wire, in bytes. Must be known ahead of time.
     fn size_on_wire(&self) -> usize;
 
-    /// Serialize the data to the wire
-    ///
-    /// Implementers MUST update the checksum field if it is present.
-    ///
-    /// Implementers MUST serialize the same number of bytes as reported by
-    /// Self::size_on_wire
-    fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> impl core::future::Future<Output = Result<(), Error<S>>>;
+    /// Serialize the data into `out`, which is exactly Self::size_on_wire
+    /// bytes long
+    fn to_wire(&self, out: &mut [u8]);
 }
 
 /// Deserialize data FROM the wire
 pub trait FromWire: Sized {
-    fn from_wire<S: Read + ErrorType>(
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> impl core::future::Future<Output = Result<Self, Error<S>>>;
+    /// Deserialize from the start of `buf`, advancing it past the bytes used.
+    ///
+    /// `buf` holds the rest of the packet. Fixed size types take what they
+    /// need, and the caller checks that nothing is left over. Types whose
+    /// size depends on the package length take everything that's left.
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError>;
+}
+
+/// Serialize `item` into the start of `out`, returning the rest of `out`
+pub(crate) fn put<'a, T: ToWire + ?Sized>(out: &'a mut [u8], item: &T) -> &'a mut [u8] {
+    let (now, rest) = out.split_at_mut(item.size_on_wire());
+    item.to_wire(now);
+    rest
+}
+
+/// Take the next `len` bytes from `buf`
+pub(crate) fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], FrameError> {
+    if buf.len() < len {
+        // The caller knows the package length, and fills it in
+        return Err(FrameError::BodyTooShort { len: 0 });
+    }
+    let (now, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(now)
+}
+
+impl<T: ToWire + ?Sized> ToWire for &T {
+    fn size_on_wire(&self) -> usize {
+        T::size_on_wire(self)
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        T::to_wire(self, out)
+    }
+}
+
+// An instruction code followed by its parameters
+impl<A: ToWire, B: ToWire> ToWire for (A, B) {
+    fn size_on_wire(&self) -> usize {
+        self.0.size_on_wire() + self.1.size_on_wire()
+    }
+
+    fn to_wire(&self, out: &mut [u8]) {
+        let rest = put(out, &self.0);
+        self.1.to_wire(rest);
+    }
 }
 
 impl ToWire for [u8] {
@@ -33,15 +65,8 @@ impl ToWire for [u8] {
         self.len()
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
-        if let Some(cksm) = cksm {
-            cksm.update(self);
-        }
-        serial.write_all(self).await.map_err(Error::Wire)
+    fn to_wire(&self, out: &mut [u8]) {
+        out.copy_from_slice(self);
     }
 }
 
@@ -54,34 +79,15 @@ macro_rules! wire_ints {
                     size_of::<$int_ty>()
                 }
 
-                async fn to_wire<S: Write + ErrorType>(
-                    &self,
-                    serial: &mut S,
-                    cksm: Option<&mut Checksum>,
-                ) -> Result<(), Error<S>> {
-                    let bytes = (*self).to_be_bytes();
-                    if let Some(cksm) = cksm {
-                        cksm.update(&bytes);
-                    }
-                    serial.write_all(&bytes).await.map_err(Error::Wire)
+                fn to_wire(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_be_bytes());
                 }
             }
 
             impl FromWire for $int_ty {
-                async fn from_wire<S: Read + ErrorType>(
-                    serial: &mut S,
-                    cksm: Option<&mut Checksum>,
-                ) -> Result<Self, Error<S>> {
-                    let mut buf = [0u8; size_of::<$int_ty>()];
-                    match serial.read_exact(&mut buf).await {
-                        Ok(()) => {}
-                        Err(ReadExactError::UnexpectedEof) => return Err(Error::EndOfFile),
-                        Err(ReadExactError::Other(w)) => return Err(Error::Wire(w)),
-                    };
-                    if let Some(cksm) = cksm {
-                        cksm.update(&buf);
-                    }
-                    Ok(<$int_ty>::from_be_bytes(buf))
+                fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+                    let bytes = <[u8; size_of::<$int_ty>()]>::from_wire(buf)?;
+                    Ok(<$int_ty>::from_be_bytes(bytes))
                 }
             }
         )*
@@ -97,21 +103,12 @@ impl ToWire for () {
         0
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        _serial: &mut S,
-        _cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
-        Ok(())
-    }
+    fn to_wire(&self, _out: &mut [u8]) {}
 }
 
 // When you receive nothing, you receive ()
 impl FromWire for () {
-    async fn from_wire<S: Read + ErrorType>(
-        _serial: &mut S,
-        _cksm: Option<&mut Checksum>,
-    ) -> Result<Self, Error<S>> {
+    fn from_wire(_buf: &mut &[u8]) -> Result<Self, FrameError> {
         Ok(())
     }
 }
@@ -121,29 +118,45 @@ impl<const N: usize> ToWire for [u8; N] {
         N
     }
 
-    async fn to_wire<S: Write + ErrorType>(
-        &self,
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<(), Error<S>> {
-        self.as_slice().to_wire(serial, cksm).await
+    fn to_wire(&self, out: &mut [u8]) {
+        self.as_slice().to_wire(out)
     }
 }
 
 impl<const N: usize> FromWire for [u8; N] {
-    async fn from_wire<S: Read + ErrorType>(
-        serial: &mut S,
-        cksm: Option<&mut Checksum>,
-    ) -> Result<Self, Error<S>> {
-        let mut buf = [0u8; N];
-        match serial.read_exact(&mut buf).await {
-            Ok(()) => {}
-            Err(ReadExactError::UnexpectedEof) => return Err(Error::EndOfFile),
-            Err(ReadExactError::Other(w)) => return Err(Error::Wire(w)),
-        };
-        if let Some(cksm) = cksm {
-            cksm.update(&buf);
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(take(buf, N)?);
+        Ok(out)
+    }
+}
+
+// Fixed size ASCII fields, padded with 0x00 (or spaces) on the wire
+impl<const N: usize> FromWire for heapless::String<N> {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let buf = take(buf, N)?;
+        let end = buf.iter().position(|b| *b == 0).unwrap_or(N);
+        let text = buf[..end].trim_ascii_end();
+        if !text.is_ascii() {
+            return Err(FrameError::InvalidText);
         }
-        Ok(buf)
+        let mut out = heapless::String::new();
+        text.iter().for_each(|b| {
+            // Can't fail: text is at most N ASCII bytes
+            let _ = out.push(*b as char);
+        });
+        Ok(out)
+    }
+}
+
+// Variable length byte strings, up to N bytes. Takes the rest of the packet.
+impl<const N: usize> FromWire for heapless::Vec<u8, N> {
+    fn from_wire(buf: &mut &[u8]) -> Result<Self, FrameError> {
+        let out = heapless::Vec::from_slice(buf).map_err(|()| FrameError::BufferTooSmall {
+            needed: buf.len(),
+            available: N,
+        })?;
+        *buf = &[];
+        Ok(out)
     }
 }
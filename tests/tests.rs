@@ -1,11 +1,134 @@
+use std::collections::VecDeque;
+
 use pretty_hex::*;
 use r503::*;
 
+/// A fake serial port: records everything written, and replays canned
+/// bytes on read.
+#[derive(Default)]
+struct MockSerial {
+    written: Vec<u8>,
+    to_read: VecDeque<u8>,
+    baud_rates: Vec<constants::BaudRate>,
+    /// Never return from a read once the canned bytes run out, like a
+    /// module that stopped talking. Otherwise reads return end of file.
+    hang: bool,
+}
+
+impl MockSerial {
+    fn with_reply(reply: &[u8]) -> Self {
+        Self {
+            to_read: reply.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl BaudRateControl for MockSerial {
+    async fn set_baud_rate(&mut self, baud_rate: constants::BaudRate) -> Result<(), Self::Error> {
+        self.baud_rates.push(baud_rate);
+        Ok(())
+    }
+}
+
+impl embedded_io_async::ErrorType for MockSerial {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_io_async::Read for MockSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.hang && self.to_read.is_empty() {
+            core::future::pending::<()>().await;
+        }
+        let mut used = 0;
+        for out in buf.iter_mut() {
+            let Some(b) = self.to_read.pop_front() else {
+                break;
+            };
+            *out = b;
+            used += 1;
+        }
+        Ok(used)
+    }
+}
+
+impl embedded_io_async::Write for MockSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+// The same port, for the blocking driver. Never hangs.
+impl embedded_io::Read for MockSerial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let used = buf.len().min(self.to_read.len());
+        for (out, b) in buf.iter_mut().zip(self.to_read.drain(..used)) {
+            *out = b;
+        }
+        Ok(used)
+    }
+}
+
+impl embedded_io::Write for MockSerial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A delay that expires as soon as everything else is waiting
+#[derive(Clone)]
+struct InstantDelay;
+
+impl embedded_hal_async::delay::DelayNs for InstantDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Builds a full acknowledge packet from address 0xFFFFFFFF
+fn ack(confirmation: u8, body: &[u8]) -> Vec<u8> {
+    ack_from(0xFFFFFFFF, confirmation, body)
+}
+
+/// Builds a full acknowledge packet from `address`
+fn ack_from(address: u32, confirmation: u8, body: &[u8]) -> Vec<u8> {
+    let mut content = vec![confirmation];
+    content.extend_from_slice(body);
+    packet(address, 0x07, &content)
+}
+
+/// Builds a data (0x02) or end of data (0x08) packet from address 0xFFFFFFFF
+fn data_packet(ident: u8, data: &[u8]) -> Vec<u8> {
+    packet(0xFFFFFFFF, ident, data)
+}
+
+/// A library slot, in a library as large as an R503-Pro's
+fn model_id(id: u16) -> constants::ModelId {
+    constants::ModelId::new(id, 1500).unwrap()
+}
+
+fn packet(address: u32, ident: u8, content: &[u8]) -> Vec<u8> {
+    let len = (content.len() + 2) as u16;
+    let mut out = vec![0xEF, 0x01];
+    out.extend_from_slice(&address.to_be_bytes());
+    out.push(ident);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(content);
+    let mut cksm = Checksum::new();
+    cksm.update(&out[6..]);
+    out.extend_from_slice(&cksm.finalize().to_be_bytes());
+    out
+}
+
 #[test]
 fn checksum_templete_num() {
     // From the manual, TempleteNum
     let part1: u16 = 0x01; // Package identifier
-    let [part2, part3] = get_u16_as_u16_parts(0x0003); // Package length
+    let [part2, part3] = 0x0003u16.to_be_bytes().map(u16::from); // Package length
     let part4: u16 = 0x1D; // Instruction code
 
     let mut checksum: u16 = 0;
@@ -19,11 +142,9 @@ fn checksum_templete_num() {
 
 #[test]
 fn checksum_templete_packet() {
-    let mut package = Package::build(
-        Identifier::Command,
-        Instruction::TempleteNum,
-        Payload::TempleteNum,
-    );
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x00, 0x05]));
+    let count = pollster::block_on(r5.template_count(&mut serial)).unwrap();
 
     let hexcfg = HexConfig {
         title: true,
@@ -32,16 +153,20 @@ fn checksum_templete_packet() {
         ..HexConfig::default()
     };
 
-    println!("Package: {:?}", &package.as_bytes().hex_conf(hexcfg));
+    println!("Package: {:?}", &serial.written.hex_conf(hexcfg));
 
-    assert_eq!(package.get_checksum(), 0x0021);
+    assert_eq!(
+        serial.written,
+        [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x03, 0x1D, 0x00, 0x21]
+    );
+    assert_eq!(count, 5);
 }
 
 #[test]
 fn checksum_gen_img() {
     // From the manual, GenImg
     let part1: u16 = 0x01; // Package identifier
-    let [part2, part3] = get_u16_as_u16_parts(0x0003); // Package length
+    let [part2, part3] = 0x0003u16.to_be_bytes().map(u16::from); // Package length
     let part4: u16 = 0x01; // Instruction code
 
     let mut checksum: u16 = 0;
@@ -52,3 +177,1057 @@ fn checksum_gen_img() {
 
     assert_eq!(checksum, 0x0005);
 }
+
+#[test]
+fn search_decodes_page_and_score() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x00, 0x2A, 0x01, 0x02]));
+    let req = SearchRequest {
+        char_buffer: constants::CharBufferId::One,
+        start_id: model_id(0),
+        count: 200,
+    };
+    let resp = pollster::block_on(r5.search(&mut serial, req)).unwrap();
+    assert_eq!(
+        resp,
+        SearchResponse {
+            model_id: model_id(42),
+            score: 0x0102
+        }
+    );
+    // Instruction, buffer, start (BE), count (BE)
+    assert_eq!(&serial.written[9..15], &[0x04, 0x01, 0x00, 0x00, 0x00, 0xC8]);
+}
+
+#[test]
+fn library_commands_encode() {
+    use constants::CharBufferId;
+
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    // Instruction, buffer, slot (BE)
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let req = StoreRequest {
+        char_buffer: CharBufferId::Two,
+        model_id: model_id(0x0123),
+    };
+    pollster::block_on(r5.store(&mut serial, req)).unwrap();
+    assert_eq!(&serial.written[6..], &[0x01, 0x00, 0x06, 0x06, 0x02, 0x01, 0x23, 0x00, 0x33]);
+
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let req = LoadCharRequest {
+        char_buffer: CharBufferId::One,
+        model_id: model_id(0x0123),
+    };
+    pollster::block_on(r5.load_char(&mut serial, req)).unwrap();
+    assert_eq!(&serial.written[9..13], &[0x07, 0x01, 0x01, 0x23]);
+
+    // Instruction, start (BE), count (BE)
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let req = DeleteCharRequest {
+        start_id: model_id(0x0102),
+        count: 3,
+    };
+    pollster::block_on(r5.delete_char(&mut serial, req)).unwrap();
+    assert_eq!(&serial.written[6..], &[0x01, 0x00, 0x07, 0x0C, 0x01, 0x02, 0x00, 0x03, 0x00, 0x1A]);
+
+    // Instruction only, score (BE) in the reply
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x01, 0x02]));
+    let resp = pollster::block_on(r5.match_templates(&mut serial)).unwrap();
+    assert_eq!(resp, MatchResponse { score: 0x0102 });
+    assert_eq!(&serial.written[6..], &[0x01, 0x00, 0x03, 0x03, 0x00, 0x07]);
+}
+
+#[test]
+fn download_template_splits_packets() {
+    let mut r5 = R503::new_with_address(0xFFFFFFFF);
+    r5.set_packet_length(constants::PacketLength::Bytes32);
+    let template: Vec<u8> = (0..70).collect();
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    pollster::block_on(r5.download_template(
+        &mut serial,
+        constants::CharBufferId::Two,
+        &template,
+    ))
+    .unwrap();
+
+    // DownChar command first
+    let (cmd, mut data) = serial.written.split_at(13);
+    assert_eq!(cmd[9..11], [0x09, 0x02]);
+
+    // Then 32 + 32 + 6 bytes of data
+    for (len, ident) in [(32, 0x02), (32, 0x02), (6, 0x08)] {
+        let (pkt, rest) = data.split_at(11 + len);
+        data = rest;
+        assert_eq!(pkt[..6], [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pkt[6], ident);
+        assert_eq!(u16::from_be_bytes([pkt[7], pkt[8]]) as usize, len + 2);
+        let mut cksm = Checksum::new();
+        cksm.update(&pkt[6..9 + len]);
+        assert_eq!(pkt[9 + len..], cksm.finalize().to_be_bytes());
+    }
+    assert!(data.is_empty());
+}
+
+#[test]
+fn download_image_checks_size() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let res = pollster::block_on(r5.download_image(&mut serial, &[0u8; 100]));
+    assert!(matches!(
+        res,
+        Err(Error::WrongSize {
+            expected: constants::PACKED_IMAGE_SIZE,
+            actual: 100
+        })
+    ));
+    assert!(serial.written.is_empty());
+
+    let image = vec![0x5Au8; constants::PACKED_IMAGE_SIZE];
+    pollster::block_on(r5.download_image(&mut serial, &image)).unwrap();
+
+    // DownImage command, then 144 packets of 128 bytes each
+    let (cmd, mut data) = serial.written.split_at(12);
+    assert_eq!(cmd[9], 0x0B);
+    let mut packets = 0;
+    while !data.is_empty() {
+        packets += 1;
+        let ident = if packets == 144 { 0x08 } else { 0x02 };
+        assert_eq!(data[6], ident);
+        data = &data[11 + 128..];
+    }
+    assert_eq!(packets, 144);
+}
+
+#[test]
+fn locked_handle_unlocks_with_password() {
+    let mut locked = LockedR503::new_with_address(0xFFFFFFFF).with_retry(retry::RetryPolicy::new(2, 0));
+    locked.set_packet_length(constants::PacketLength::Bytes64);
+
+    let mut serial = MockSerial::with_reply(&ack(0x13, &[]));
+    let res = pollster::block_on(locked.unlock(&mut serial, 0x1234_5678));
+    let Err((locked, Error::BadConfirmation(constants::ConfirmationCode::WrongPassword))) = res else {
+        panic!("wrong password accepted");
+    };
+    assert_eq!(serial.written[9..14], [0x13, 0x12, 0x34, 0x56, 0x78]);
+
+    // The settings carry over to the unlocked handle
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let r5 = pollster::block_on(locked.unlock(&mut serial, 0x1234_5678)).unwrap();
+    assert_eq!(r5.address(), 0xFFFFFFFF);
+    assert_eq!(r5.packet_length(), constants::PacketLength::Bytes64);
+    assert_eq!(r5.retry_policy(), retry::RetryPolicy::new(2, 0));
+}
+
+#[test]
+fn system_parameters_decode() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let body = [
+        0x00, 0x0C, // status: PWD + ImgBufStat
+        0x00, 0x09, // system id
+        0x00, 0xC8, // library size
+        0x00, 0x03, // security level
+        0xFF, 0xFF, 0xFF, 0xFF, // address
+        0x00, 0x02, // packet length
+        0x00, 0x06, // baud
+    ];
+    let mut serial = MockSerial::with_reply(&ack(0x00, &body));
+    let params = pollster::block_on(r5.read_system_parameter(&mut serial)).unwrap();
+    assert!(!params.status.busy());
+    assert!(params.status.password_verified());
+    assert!(params.status.image_buffer_valid());
+    assert_eq!(params.system_id, 0x0009);
+    assert_eq!(params.library_size, 200);
+    assert_eq!(params.security_level, constants::SecurityLevel::Level3);
+    assert_eq!(params.address, 0xFFFFFFFF);
+    assert_eq!(params.packet_length, constants::PacketLength::Bytes128);
+    assert_eq!(params.baud_rate.bits_per_second(), 57_600);
+}
+
+/// ReadSysPara body with the given baud setting
+fn sys_para(baud: u8) -> Vec<u8> {
+    vec![
+        0x00, 0x00, 0x00, 0x09, 0x00, 0xC8, 0x00, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x02, 0x00,
+        baud,
+    ]
+}
+
+#[test]
+fn reconfigure_baud_rate() {
+    use constants::BaudRate;
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(InstantDelay);
+
+    // Module follows along
+    let mut reply = ack(0x00, &sys_para(6));
+    reply.extend(ack(0x00, &[]));
+    reply.extend(ack(0x00, &sys_para(12)));
+    let mut serial = MockSerial::with_reply(&reply);
+    pollster::block_on(r5.reconfigure_baud_rate(&mut serial, BaudRate::Rate115200)).unwrap();
+    assert_eq!(serial.baud_rates, [BaudRate::Rate115200]);
+    // ReadSysPara, then SetSysPara(4, 12)
+    assert_eq!(serial.written[12 + 9..12 + 12], [0x0E, 0x04, 0x0C]);
+
+    // Module ignores the change until power cycled: host goes back to 57600
+    let mut reply = ack(0x00, &sys_para(6));
+    reply.extend(ack(0x00, &[]));
+    reply.extend(ack(0x00, &sys_para(6)));
+    reply.extend(ack(0x00, &sys_para(6)));
+    let mut serial = MockSerial::with_reply(&reply);
+    let res = pollster::block_on(r5.reconfigure_baud_rate(&mut serial, BaudRate::Rate115200));
+    assert!(res.is_err());
+    assert_eq!(serial.baud_rates, [BaudRate::Rate115200, BaudRate::Rate57600]);
+}
+
+#[test]
+fn reconfigure_packet_length() {
+    use constants::PacketLength;
+    let mut r5 = R503::new_with_address(0xFFFFFFFF);
+
+    // Rejected: the handle keeps splitting at the old size
+    let mut serial = MockSerial::with_reply(&ack(0x1A, &[]));
+    let res = pollster::block_on(r5.reconfigure_packet_length(&mut serial, PacketLength::Bytes32));
+    assert!(res.is_err());
+    assert_eq!(r5.packet_length(), PacketLength::Bytes128);
+
+    // Accepted, but not in effect yet: still the old size
+    let mut serial = MockSerial::with_reply(&[ack(0x00, &[]), ack(0x00, &sys_para(6))].concat());
+    let len = pollster::block_on(r5.reconfigure_packet_length(&mut serial, PacketLength::Bytes32)).unwrap();
+    assert_eq!(serial.written[9..12], [0x0E, 0x06, 0x00]);
+    assert_eq!((len, r5.packet_length()), (PacketLength::Bytes128, PacketLength::Bytes128));
+
+    // Switches once the module reports the new size
+    let mut params = sys_para(6);
+    params[13] = 0x00;
+    let mut serial = MockSerial::with_reply(&[ack(0x00, &[]), ack(0x00, &params)].concat());
+    let len = pollster::block_on(r5.reconfigure_packet_length(&mut serial, PacketLength::Bytes32)).unwrap();
+    assert_eq!((len, r5.packet_length()), (PacketLength::Bytes32, PacketLength::Bytes32));
+
+    // Back to whatever the module is configured for
+    let mut serial = MockSerial::with_reply(&ack(0x00, &sys_para(6)));
+    let len = pollster::block_on(r5.sync_packet_length(&mut serial)).unwrap();
+    assert_eq!((len, r5.packet_length()), (PacketLength::Bytes128, PacketLength::Bytes128));
+}
+
+#[test]
+fn set_address_rebinds_handle() {
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_retry(retry::RetryPolicy::new(3, 0));
+    // An ack from the old address is rejected, and the old handle comes back
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    let Err((r5, _)) = pollster::block_on(r5.set_address(&mut serial, 0x1234_5678)) else {
+        panic!("ack from the old address accepted");
+    };
+    assert_eq!(r5.address(), 0xFFFFFFFF);
+
+    let mut serial = MockSerial::with_reply(&ack_from(0x1234_5678, 0x00, &[]));
+    let Ok(r5) = pollster::block_on(r5.set_address(&mut serial, 0x1234_5678)) else {
+        panic!("address change failed");
+    };
+    assert_eq!(r5.address(), 0x1234_5678);
+    assert_eq!(r5.retry_policy().max_attempts, 3);
+    // Sent to the old address
+    assert_eq!(serial.written[2..6], [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(serial.written[9..14], [0x15, 0x12, 0x34, 0x56, 0x78]);
+}
+
+#[test]
+fn notepad_record_store() {
+    use notepad::{NotepadPage, PageState, RecordStore, StoreError, NOTEPAD_PAGES};
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    assert!(NotepadPage::new(NOTEPAD_PAGES).is_none());
+
+    // Blank notepad, then one write
+    let mut reply = vec![];
+    for _ in 0..NOTEPAD_PAGES {
+        reply.extend(ack(0x00, &[0u8; 32]));
+    }
+    reply.extend(ack(0x00, &[]));
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut store = pollster::block_on(RecordStore::load(&r5, &mut serial)).unwrap();
+    assert_eq!(store.records().count(), 0);
+    assert_eq!(store.corrupt_pages().count(), 0);
+    pollster::block_on(store.put(&r5, &mut serial, 7, b"user-42")).unwrap();
+    assert_eq!(store.get(7).unwrap().value(), b"user-42");
+
+    // Page number, then the encoded page
+    let write = &serial.written[serial.written.len() - 36..serial.written.len() - 2];
+    assert_eq!(write[0], 0x18);
+    assert_eq!(write[1], 0x00);
+    let mut page: [u8; 32] = write[2..].try_into().unwrap();
+
+    // Unchanged values aren't rewritten
+    let before = serial.written.len();
+    pollster::block_on(store.put(&r5, &mut serial, 7, b"user-42")).unwrap();
+    assert_eq!(serial.written.len(), before);
+
+    // Too long for one page
+    let res = pollster::block_on(store.put(&r5, &mut serial, 8, &[0u8; 25]));
+    assert!(matches!(res, Err(StoreError::TooLong)));
+
+    // Read the record back, next to a corrupted copy of it
+    let mut reply = ack(0x00, &page);
+    page[10] ^= 0x01;
+    reply.extend(ack(0x00, &page));
+    for _ in 2..NOTEPAD_PAGES {
+        reply.extend(ack(0x00, &[0xFFu8; 32]));
+    }
+    let mut serial = MockSerial::with_reply(&reply);
+    let store = pollster::block_on(RecordStore::load(&r5, &mut serial)).unwrap();
+    assert_eq!(store.get(7).unwrap().value(), b"user-42");
+    assert_eq!(store.records().count(), 1);
+    assert_eq!(
+        store.corrupt_pages().collect::<Vec<_>>(),
+        [NotepadPage::new(1).unwrap()]
+    );
+    assert_eq!(store.page_states()[2], PageState::Free { writes: 0 });
+
+    // Foreign pages are left alone, even when nothing else is free
+    let mut reply = vec![];
+    for _ in 0..NOTEPAD_PAGES {
+        reply.extend(ack(0x00, &[0x42u8; 32]));
+    }
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut store = pollster::block_on(RecordStore::load(&r5, &mut serial)).unwrap();
+    let before = serial.written.len();
+    let res = pollster::block_on(store.put(&r5, &mut serial, 7, b"user-42"));
+    assert!(matches!(res, Err(StoreError::Full)));
+    assert_eq!(serial.written.len(), before);
+}
+
+#[test]
+fn product_info_and_versions() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut body = vec![];
+    body.extend_from_slice(b"R503\0\0\0\0\0\0\0\0\0\0\0\0");
+    body.extend_from_slice(b"B001");
+    body.extend_from_slice(b"12345   ");
+    body.extend_from_slice(&[1, 2]);
+    body.extend_from_slice(b"FPS\0\0\0\0\0");
+    body.extend_from_slice(&[0x00, 0xC0, 0x00, 0xC0, 0x02, 0x00, 0x00, 0xC8]);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &body));
+    let info = pollster::block_on(r5.read_product_info(&mut serial)).unwrap();
+    assert_eq!(info.module_model, "R503");
+    assert_eq!(info.batch_number, "B001");
+    assert_eq!(info.serial_number, "12345");
+    assert_eq!(info.hardware_version, [1, 2]);
+    assert_eq!(info.sensor_model, "FPS");
+    assert_eq!((info.sensor_width, info.sensor_height), (192, 192));
+    assert_eq!(info.template_size, 512);
+    assert_eq!(info.library_size, 200);
+
+    let mut ver = [0u8; 32];
+    ver[..6].copy_from_slice(b"V1.2.3");
+    let mut serial = MockSerial::with_reply(&ack(0x00, &ver));
+    let fw = pollster::block_on(r5.get_firmware_version(&mut serial)).unwrap();
+    assert_eq!(fw, "V1.2.3");
+    assert_eq!(serial.written[9], 0x3A);
+}
+
+#[test]
+fn read_info_page_streams_data() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let page: Vec<u8> = (0..512).map(|i| i as u8).collect();
+    let mut reply = ack(0x00, &[]);
+    for (i, chunk) in page.chunks(128).enumerate() {
+        let ident = if i == 3 { 0x08 } else { 0x02 };
+        reply.extend(data_packet(ident, chunk));
+    }
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut out = [0u8; 512];
+    let used = pollster::block_on(r5.read_info_page(&mut serial, &mut out)).unwrap();
+    assert_eq!(used, 512);
+    assert_eq!(out[..], page[..]);
+}
+
+#[test]
+fn cancel_drains_auto_identify() {
+    use auto::{AutoIdentify, AutoIdentifyConfig};
+    // A "collect image" step, then the Cancel acknowledgement
+    let mut reply = ack(0x00, &[0x01, 0x00, 0x00, 0x00, 0x00]);
+    reply.extend(ack(0x00, &[]));
+    let mut serial = MockSerial::with_reply(&reply);
+
+    let mut identify = AutoIdentify::new(0xFFFFFFFF, &mut serial);
+    pollster::block_on(identify.start(AutoIdentifyConfig::default())).unwrap();
+    pollster::block_on(identify.cancel()).unwrap();
+
+    assert!(serial.to_read.is_empty());
+    // AutoIdentify command (17 bytes), then Cancel
+    assert_eq!(serial.written[17 + 9], 0x30);
+
+    // The operation's own error acknowledgement isn't taken for the Cancel one
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(InstantDelay);
+    let mut serial = MockSerial::with_reply(&[ack(0x26, &[]), ack(0x00, &[])].concat());
+    pollster::block_on(r5.cancel(&mut serial)).unwrap();
+    assert!(serial.to_read.is_empty());
+
+    // A module that stays quiet
+    let mut serial = MockSerial {
+        hang: true,
+        ..Default::default()
+    };
+    assert!(matches!(pollster::block_on(r5.cancel(&mut serial)), Err(Error::Timeout)));
+
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[]));
+    pollster::block_on(r5.handshake(&mut serial)).unwrap();
+    assert_eq!(serial.written[9], 0x40);
+}
+
+#[test]
+fn timeout_and_resync() {
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(InstantDelay);
+
+    // Half an acknowledgement, then silence
+    let reply = ack(0x00, &[0x00, 0x05]);
+    let mut serial = MockSerial::with_reply(&reply[..7]);
+    serial.hang = true;
+    let res = pollster::block_on(r5.template_count(&mut serial));
+    assert!(matches!(res, Err(Error::Timeout)));
+
+    // The next command isn't confused by the stale bytes
+    serial.to_read.extend([0x55, 0xAA, 0x55]);
+    assert_eq!(pollster::block_on(r5.resync(&mut serial)).unwrap(), 3);
+    serial.to_read.extend(ack(0x00, &[0x00, 0x05]));
+    assert_eq!(pollster::block_on(r5.template_count(&mut serial)).unwrap(), 5);
+
+    // Auto steps time out too
+    use auto::{AutoIdentify, AutoIdentifyConfig};
+    let mut identify = AutoIdentify::new(0xFFFFFFFF, &mut serial).with_delay(InstantDelay);
+    pollster::block_on(identify.start(AutoIdentifyConfig::default())).unwrap();
+    assert!(matches!(pollster::block_on(identify.wait_auto()), Err(Error::Timeout)));
+
+    // Without a delay source there's no way to tell the line went quiet
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    serial.to_read.extend([0x55, 0xAA]);
+    assert_eq!(pollster::block_on(r5.resync(&mut serial)).unwrap(), 0);
+}
+
+#[test]
+fn skips_noise_before_header() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+
+    // Power-on ready byte, and a stray 0xEF just before the real header
+    let mut reply = vec![0x55, 0x00, 0xEF];
+    reply.extend(ack(0x00, &[0x00, 0x05]));
+    let mut serial = MockSerial::with_reply(&reply);
+    assert_eq!(pollster::block_on(r5.template_count(&mut serial)).unwrap(), 5);
+    assert_eq!(r5.take_skipped_bytes(), 3);
+    assert_eq!(r5.take_skipped_bytes(), 0);
+
+    // Too much noise
+    let mut reply = vec![0x00; MAX_HEADER_SKIP + 1];
+    reply.extend(ack(0x00, &[0x00, 0x05]));
+    let mut serial = MockSerial::with_reply(&reply);
+    let res = pollster::block_on(r5.template_count(&mut serial));
+    assert!(matches!(res, Err(Error::HeaderNotFound { skipped: MAX_HEADER_SKIP })));
+}
+
+#[test]
+fn package_length_checked() {
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+
+    // An error confirmation with a stray body, an extra byte, a missing byte
+    let mut reply = ack(0x01, &[0x00, 0x05]);
+    reply.extend(ack(0x00, &[0x00, 0x05, 0x09]));
+    reply.extend(ack(0x00, &[0x05]));
+    reply.extend(ack(0x00, &[0x00, 0x05]));
+    let mut serial = MockSerial::with_reply(&reply);
+
+    let res = pollster::block_on(r5.template_count(&mut serial));
+    assert!(matches!(
+        res,
+        Err(Error::BadConfirmation(constants::ConfirmationCode::ErrorCode))
+    ));
+    let res = pollster::block_on(r5.template_count(&mut serial));
+    assert!(matches!(res, Err(Error::BodyTooLong { len: 3, used: 2 })));
+    let res = pollster::block_on(r5.template_count(&mut serial));
+    assert!(matches!(res, Err(Error::BodyTooShort { len: 1 })));
+    // ...and each packet was read in full
+    assert_eq!(pollster::block_on(r5.template_count(&mut serial)).unwrap(), 5);
+    assert!(serial.to_read.is_empty());
+
+    // Bodies sized by the length field
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[1, 2, 3]));
+    let resp = pollster::block_on(Response::<heapless::Vec<u8, 8>>::from_wire(&mut serial)).unwrap();
+    assert_eq!(resp.body().unwrap()[..], [1, 2, 3]);
+}
+
+#[test]
+fn errors_carry_details() {
+    // Reply from another module
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack_from(0x12345678, 0x00, &[0x00, 0x05]));
+    let err = pollster::block_on(r5.template_count(&mut serial)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::WrongAddress {
+            expected: 0xFFFFFFFF,
+            actual: 0x12345678
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "packet from address 0x12345678, expected 0xFFFFFFFF"
+    );
+
+    // Unknown security level in the system parameters
+    let mut body = sys_para(6);
+    body[7] = 0x09;
+    let mut serial = MockSerial::with_reply(&ack(0x00, &body));
+    let err = pollster::block_on(r5.read_system_parameter(&mut serial)).unwrap_err();
+    assert_eq!(err.to_string(), "unknown SecurityLevel 0x9");
+
+    // Data packet bigger than the buffer
+    let mut serial = MockSerial::with_reply(&data_packet(0x08, &[0u8; 64]));
+    let mut out = [0u8; 32];
+    let err = pollster::block_on(r5.stream_image(&mut serial, &mut out)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::BufferTooSmall {
+            needed: 64,
+            available: 32
+        }
+    ));
+
+    // AutoIdentify steps out of order
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x02, 0x00, 0x00, 0x00, 0x00]));
+    let mut identify = auto::AutoIdentify::new(0xFFFFFFFF, &mut serial);
+    let err = pollster::block_on(identify.wait_auto()).unwrap_err();
+    assert!(matches!(err, Error::WrongStep { expected: 0x01, actual: 0x02 }));
+
+    // Domain errors display like the device errors they wrap
+    let err: notepad::StoreError<MockSerial> = Error::Timeout.into();
+    assert_eq!(err.to_string(), "timed out waiting for the module");
+    let err = enroll::EnrollError::<MockSerial>::BadSampleCount { samples: 7 };
+    assert_eq!(err.to_string(), "7 samples, expected 2 to 6");
+}
+
+/// An output pin that shares its level with the test
+struct MockPin(std::rc::Rc<std::cell::Cell<bool>>);
+
+impl embedded_hal::digital::ErrorType for MockPin {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_hal::digital::OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+#[test]
+fn power_cycle_waits_off_time() {
+    use power::{PowerControl, PowerError, Polarity};
+    use std::{cell::Cell, rc::Rc};
+
+    let level = Rc::new(Cell::new(false));
+    let now = Cell::new(10_000u64);
+    let mut power = PowerControl::new(MockPin(level.clone()), Polarity::ActiveLow, || now.get()).unwrap();
+    // Active low: off is high
+    assert!(level.get());
+
+    // Without a delay source, switching on too early is refused
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&[constants::READY]);
+    now.set(11_500);
+    let res = pollster::block_on(r5.power_on(&mut serial, &mut power));
+    assert!(matches!(res, Err(PowerError::TooSoon { remaining_ms: 500 })));
+    assert!(level.get());
+
+    now.set(12_000);
+    pollster::block_on(r5.power_on(&mut serial, &mut power)).unwrap();
+    assert!(!level.get());
+    assert!(power.is_on());
+    assert!(serial.to_read.is_empty());
+
+    // With one, the rest of the off time is waited out, but the module
+    // never says it's ready
+    power.power_off().unwrap();
+    assert_eq!(power.off_remaining_ms(), 2000);
+    let r5 = r5.with_delay(InstantDelay);
+    let mut serial = MockSerial::with_reply(&[0x00]);
+    serial.hang = true;
+    let res = pollster::block_on(r5.power_on(&mut serial, &mut power));
+    assert!(matches!(res, Err(PowerError::Device(Error::Timeout))));
+    assert!(!level.get());
+}
+
+/// A WAKEUP line where every wait is over right away
+#[derive(Default)]
+struct MockTouch {
+    waits: Vec<&'static str>,
+}
+
+impl embedded_hal::digital::ErrorType for MockTouch {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_hal_async::digital::Wait for MockTouch {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.waits.push("high");
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.waits.push("low");
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn identify_on_touch_powers_module() {
+    use auto::AutoIdentifyConfig;
+    use power::{PowerControl, Polarity};
+    use std::{cell::Cell, rc::Rc};
+    use touch::TouchSensor;
+
+    let level = Rc::new(Cell::new(true));
+    let mut power = PowerControl::new(MockPin(level.clone()), Polarity::ActiveHigh, || 5_000u64).unwrap();
+    assert!(!level.get());
+    let mut touch = TouchSensor::new(MockTouch::default());
+
+    // Ready, then collect image, generate feature and a match on id 4
+    let mut reply = vec![constants::READY];
+    reply.extend(ack(0x00, &[0x01, 0x00, 0x00, 0x00, 0x00]));
+    reply.extend(ack(0x00, &[0x02, 0x00, 0x00, 0x00, 0x00]));
+    reply.extend(ack(0x00, &[0x03, 0x00, 0x04, 0x00, 0x80]));
+    let mut serial = MockSerial::with_reply(&reply);
+
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(InstantDelay);
+    let cfg = AutoIdentifyConfig::default();
+    let resp = pollster::block_on(r5.identify_on_touch(&mut serial, &mut touch, &mut power, cfg)).unwrap();
+    assert_eq!((resp.model_id, resp.score), (model_id(4), 0x80));
+
+    // Lifted, touched, then powered off again
+    assert_eq!(touch.release().waits, ["high", "low"]);
+    assert!(!power.is_on());
+    assert!(!level.get());
+    assert!(serial.to_read.is_empty());
+
+    // A failed identification is a device error, not a power fault
+    let mut touch = TouchSensor::new(MockTouch::default());
+    let mut reply = vec![constants::READY];
+    reply.extend(ack(0x09, &[0x03, 0x00, 0x00, 0x00, 0x00]));
+    let mut serial = MockSerial::with_reply(&reply);
+    let cfg = AutoIdentifyConfig::default();
+    let res = pollster::block_on(r5.identify_on_touch(&mut serial, &mut touch, &mut power, cfg));
+    assert!(matches!(
+        res,
+        Err(touch::TouchError::Device(Error::BadConfirmation(
+            constants::ConfirmationCode::FailToFindMatchingFinger
+        )))
+    ));
+    assert!(!power.is_on());
+}
+
+#[test]
+fn blocking_driver() {
+    use auto::AutoIdentifyConfig;
+
+    let r5 = blocking::R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x00, 0x05]));
+    assert_eq!(r5.template_count(&mut serial).unwrap(), 5);
+    assert_eq!(
+        serial.written,
+        [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x03, 0x1D, 0x00, 0x21]
+    );
+
+    // Errors come back for the blocking port
+    let mut serial = MockSerial::with_reply(&ack(0x01, &[]));
+    let res: Result<(), Error<MockSerial>> = r5.handshake(&mut serial);
+    assert!(matches!(res, Err(Error::BadConfirmation(_))));
+
+    let mut reply = vec![0x55];
+    reply.extend(ack(0x00, &[0x01, 0x00, 0x00, 0x00, 0x00]));
+    reply.extend(ack(0x00, &[0x03, 0x00, 0x07, 0x00, 0x40]));
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut identify = blocking::AutoIdentify::new(0xFFFFFFFF, &mut serial);
+    identify.start(AutoIdentifyConfig::default()).unwrap();
+    let resp = identify.wait_auto().unwrap();
+    assert_eq!((resp.model_id, resp.score), (model_id(7), 0x40));
+    assert_eq!(identify.take_skipped_bytes(), 1);
+}
+
+#[test]
+fn handles_are_sync() {
+    // Futures holding a handle across an await stay Send
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<R503>();
+    assert_sync::<R503<InstantDelay>>();
+    assert_sync::<blocking::R503>();
+}
+
+#[test]
+fn frame_encode_and_decode() {
+    use frame::{Decoder, FrameError};
+
+    // The TempleteNum command from the manual
+    let cmd = Command::new(0xFFFFFFFF, constants::Commands::TempleteNum, ());
+    let mut buf = [0u8; 16];
+    let len = cmd.encode(&mut buf).unwrap();
+    assert_eq!(
+        buf[..len],
+        [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x03, 0x1D, 0x00, 0x21]
+    );
+    assert!(matches!(
+        cmd.encode(&mut buf[..8]),
+        Err(FrameError::BufferTooSmall { needed: 12, available: 8 })
+    ));
+
+    // Noise, a packet split across slices, a corrupted packet, then a
+    // packet in the same slice as the one before it
+    let first = ack(0x00, &[0x00, 0x05]);
+    let mut bad = ack(0x00, &[0x00, 0x06]);
+    bad[10] ^= 0x01;
+    let mut stream = vec![0x55, 0xEF];
+    stream.extend(&first);
+    stream.extend(&bad);
+    stream.extend(ack(0x00, &[0x00, 0x07]));
+
+    let mut dec = Decoder::new();
+    let (head, tail) = stream.split_at(7);
+    let mut input = head;
+    assert!(dec.feed(&mut input).is_none());
+    let mut input = tail;
+    let packet = dec.feed(&mut input).unwrap().unwrap();
+    assert_eq!((packet.address, packet.ident, packet.skipped), (0xFFFFFFFF, 0x07, 2));
+    assert_eq!(packet.content, [0x00, 0x00, 0x05]);
+    let resp = Response::<u16>::from_packet(&packet).unwrap();
+    assert_eq!(resp.body(), Some(&5));
+
+    assert!(matches!(
+        dec.feed(&mut input),
+        Some(Err(FrameError::BadChecksum { .. }))
+    ));
+    let packet = dec.feed(&mut input).unwrap().unwrap();
+    assert_eq!(packet.content, [0x00, 0x00, 0x07]);
+    assert!(input.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[tokio::test]
+async fn tokio_serial_transport() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // A pty pair stands in for the module's UART
+    let (host, mut module) = tokio_serial::SerialStream::pair().unwrap();
+    let mut serial = std_serial::TokioSerial::new(host);
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_delay(std_serial::TokioDelay);
+
+    module.write_all(&ack(0x00, &[0x00, 0x05])).await.unwrap();
+    assert_eq!(r5.template_count(&mut serial).await.unwrap(), 5);
+
+    let mut written = [0u8; 12];
+    module.read_exact(&mut written).await.unwrap();
+    assert_eq!(
+        written,
+        [0xEF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x03, 0x1D, 0x00, 0x21]
+    );
+}
+
+#[test]
+fn retry_policy() {
+    use retry::RetryPolicy;
+
+    let r5 = R503::new_with_address(0xFFFFFFFF).with_retry(RetryPolicy::new(3, 10));
+
+    // Rejected by the module, then a corrupted reply, then success
+    let mut bad = ack(0x00, &[0x00, 0x06]);
+    bad[11] ^= 0x01;
+    let mut reply = ack(0x01, &[]);
+    reply.extend(bad.clone());
+    reply.extend(ack(0x00, &[0x00, 0x05]));
+    let mut serial = MockSerial::with_reply(&reply);
+    assert_eq!(pollster::block_on(r5.template_count(&mut serial)).unwrap(), 5);
+    assert_eq!(serial.written.len(), 3 * 12);
+
+    // Store may have run, so a lost reply isn't retried...
+    let req = || StoreRequest {
+        char_buffer: constants::CharBufferId::One,
+        model_id: model_id(1),
+    };
+    let mut serial = MockSerial::with_reply(&bad);
+    let res = pollster::block_on(r5.store(&mut serial, req()));
+    assert!(matches!(res, Err(Error::BadChecksum { .. })));
+    assert_eq!(serial.written.len(), 15);
+
+    // RegModel writes the merged template back over its inputs
+    let mut bad_ack = ack(0x00, &[]);
+    bad_ack[9] ^= 0x01;
+    let mut serial = MockSerial::with_reply(&bad_ack);
+    let res = pollster::block_on(r5.generate_template(&mut serial));
+    assert!(matches!(res, Err(Error::BadChecksum { .. })));
+    assert_eq!(serial.written.len(), 12);
+
+    // Uploads are followed by data packets, which a resend would interleave
+    let mut serial = MockSerial::with_reply(&bad_ack);
+    let res = pollster::block_on(r5.upload_template(&mut serial, constants::CharBufferId::One));
+    assert!(matches!(res, Err(Error::BadChecksum { .. })));
+    assert_eq!(serial.written.len(), 13);
+
+    // ...but a command the module never got is
+    let mut reply = ack(0x01, &[]);
+    reply.extend(ack(0x00, &[]));
+    let mut serial = MockSerial::with_reply(&reply);
+    pollster::block_on(r5.store(&mut serial, req())).unwrap();
+    assert_eq!(serial.written.len(), 2 * 15);
+
+    // Give up after max_attempts
+    let mut serial = MockSerial::with_reply(&[ack(0x01, &[]), ack(0x01, &[]), ack(0x01, &[])].concat());
+    let res = pollster::block_on(r5.handshake(&mut serial));
+    assert!(matches!(
+        res,
+        Err(Error::BadConfirmation(constants::ConfirmationCode::ErrorCode))
+    ));
+    assert!(serial.to_read.is_empty());
+}
+
+#[test]
+fn auto_enroll_events() {
+    use auto::{AutoEnroll, AutoEnrollConfig, EnrollEvent};
+    use constants::AutoEnrollStep;
+
+    // Steps arrive in any order, and some never do
+    let mut reply = ack(0x00, &[0x01, 0x00, 0x00]);
+    reply.extend(ack(0x00, &[0x02, 0x00, 0x00]));
+    reply.extend(ack(0x00, &[0x0E, 0x00, 0x00]));
+    reply.extend(ack(0x00, &[0x0F, 0x00, 0x07]));
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut events = vec![];
+    let mut enroll = AutoEnroll::new(0xFFFFFFFF, &mut serial);
+    let id = pollster::block_on(enroll.run(AutoEnrollConfig::default(), |e| events.push(e))).unwrap();
+    assert_eq!(id, model_id(7));
+    assert_eq!(
+        events,
+        [
+            EnrollEvent::Step(AutoEnrollStep::CollectImage1),
+            EnrollEvent::Step(AutoEnrollStep::GenerateFeature1),
+            EnrollEvent::Step(AutoEnrollStep::MergeFeature),
+            EnrollEvent::Stored { model_id: model_id(7) },
+        ]
+    );
+
+    // Only the final acknowledgement, without status reports, from a larger
+    // library
+    let cfg = AutoEnrollConfig {
+        return_status: false,
+        ..Default::default()
+    };
+    let mut serial = MockSerial::with_reply(&ack(0x00, &[0x00, 0x01, 0x2C]));
+    let mut enroll = blocking::AutoEnroll::new(0xFFFFFFFF, &mut serial);
+    enroll.start(cfg).unwrap();
+    assert_eq!(enroll.next_event().unwrap(), EnrollEvent::Stored { model_id: model_id(300) });
+}
+
+#[test]
+fn model_ids_bounded_by_library() {
+    use auto::{AutoEnrollLocation, AutoIdentifyRange};
+
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let mut serial = MockSerial::with_reply(&ack(0x00, &sys_para(0x06)));
+    let params = pollster::block_on(r5.read_system_parameter(&mut serial)).unwrap();
+    assert_eq!(params.model_id(199).map(u16::from), Some(199));
+    assert_eq!(params.model_id(200), None);
+
+    // AutoEnroll only has a byte for the location
+    let pro = constants::ModelId::new(300, 1500).unwrap();
+    assert!(AutoEnrollLocation::specific(pro).is_none());
+    assert!(AutoEnrollLocation::specific(params.model_id(7).unwrap()).is_some());
+
+    // AutoIdentify has a byte for each end of the range
+    assert!(AutoIdentifyRange::new(model_id(0), model_id(255)).is_some());
+    assert!(AutoIdentifyRange::new(model_id(0), pro).is_none());
+    assert!(AutoIdentifyRange::new(model_id(9), model_id(8)).is_none());
+}
+
+#[test]
+fn manual_enroll_pipeline() {
+    use constants::ConfirmationCode;
+    use enroll::{EnrollConfig, EnrollError, EnrollProgress};
+
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let cfg = EnrollConfig {
+        samples: 2,
+        ..EnrollConfig::new(model_id(12))
+    };
+    let reply = [
+        // Sample 1: no finger yet, then an image, and no duplicate
+        ack(0x02, &[]),
+        ack(0x00, &[]),
+        ack(0x00, &[]),
+        ack(0x00, &sys_para(0x06)),
+        ack(0x09, &[]),
+        ack(0x02, &[]),
+        // Sample 2: a failed capture, then too few minutiae, each retaken
+        // after a lift
+        ack(0x03, &[]),
+        ack(0x02, &[]),
+        ack(0x00, &[]),
+        ack(0x07, &[]),
+        ack(0x00, &[]),
+        ack(0x02, &[]),
+        ack(0x00, &[]),
+        ack(0x00, &[]),
+        // RegModel, Store
+        ack(0x00, &[]),
+        ack(0x00, &[]),
+    ]
+    .concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let mut events = vec![];
+    pollster::block_on(r5.enroll(&mut serial, cfg.clone(), |e| events.push(e))).unwrap();
+    assert!(serial.to_read.is_empty());
+    assert_eq!(
+        events,
+        [
+            EnrollProgress::PlaceFinger { sample: 1 },
+            EnrollProgress::SampleTaken { sample: 1 },
+            EnrollProgress::LiftFinger,
+            EnrollProgress::PlaceFinger { sample: 2 },
+            EnrollProgress::SampleRejected {
+                sample: 2,
+                code: ConfirmationCode::FailToEnrollFinger,
+            },
+            EnrollProgress::LiftFinger,
+            EnrollProgress::PlaceFinger { sample: 2 },
+            EnrollProgress::SampleRejected {
+                sample: 2,
+                code: ConfirmationCode::FailToGenerateCharacterLacknessOfCharacterPointOrOverSmallness,
+            },
+            EnrollProgress::LiftFinger,
+            EnrollProgress::PlaceFinger { sample: 2 },
+            EnrollProgress::SampleTaken { sample: 2 },
+            EnrollProgress::TemplateGenerated,
+            EnrollProgress::Stored { model_id: model_id(12) },
+        ]
+    );
+    // Stored into the configured slot
+    assert_eq!(serial.written[serial.written.len() - 6..][..4], [0x06, 0x01, 0x00, 0x0C]);
+
+    // The finger is already enrolled
+    let reply = [ack(0x00, &[]), ack(0x00, &[]), ack(0x00, &sys_para(0x06)), ack(0x00, &[0x00, 0x05, 0x00, 0x50])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let res = blocking::R503::new_with_address(0xFFFFFFFF).enroll(&mut serial, cfg, |_| {});
+    assert!(matches!(res, Err(EnrollError::Duplicate { model_id: id, score: 0x50 }) if id == model_id(5)));
+
+    let cfg = EnrollConfig {
+        samples: 7,
+        ..EnrollConfig::new(model_id(0))
+    };
+    let res = pollster::block_on(r5.enroll(&mut MockSerial::default(), cfg, |_| {}));
+    assert!(matches!(res, Err(EnrollError::BadSampleCount { samples: 7 })));
+}
+
+#[test]
+fn manual_verify_and_identify() {
+    use verify::MatchResult;
+
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    // GetImage, GenChar, LoadChar, Match
+    let reply = [ack(0x02, &[]), ack(0x00, &[]), ack(0x00, &[]), ack(0x00, &[]), ack(0x00, &[0x00, 0x90])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let res = pollster::block_on(r5.verify(&mut serial, model_id(3))).unwrap();
+    assert_eq!(res, Some(MatchResult { model_id: model_id(3), score: 0x90 }));
+    // LoadChar into CharBuffer 2, template 3
+    let load = Command::new(0xFFFFFFFF, constants::Commands::LoadChar, (constants::CharBufferId::Two, 3u16));
+    let mut buf = [0u8; 32];
+    let len = load.encode(&mut buf).unwrap();
+    assert_eq!(&serial.written[37..37 + len], &buf[..len]);
+
+    let reply = [ack(0x00, &[]), ack(0x00, &[]), ack(0x00, &[]), ack(0x08, &[0x00, 0x00])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    assert_eq!(pollster::block_on(r5.verify(&mut serial, model_id(3))).unwrap(), None);
+
+    // Search only slots 100 through 149
+    let reply = [ack(0x00, &[]), ack(0x00, &[]), ack(0x00, &[0x00, 0x70, 0x00, 0x64])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let r5 = blocking::R503::new_with_address(0xFFFFFFFF);
+    let res = r5.identify(&mut serial, model_id(100)..=model_id(149)).unwrap();
+    assert_eq!(res, Some(MatchResult { model_id: model_id(0x70), score: 0x64 }));
+    let search = Command::new(
+        0xFFFFFFFF,
+        constants::Commands::Search,
+        (constants::CharBufferId::One, (100u16, 50u16)),
+    );
+    let len = search.encode(&mut buf).unwrap();
+    assert_eq!(&serial.written[25..], &buf[..len]);
+
+    let reply = [ack(0x00, &[]), ack(0x00, &[]), ack(0x09, &[])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    assert_eq!(r5.identify(&mut serial, model_id(0)..=model_id(199)).unwrap(), None);
+
+    // An empty range doesn't wait for a finger
+    let mut serial = MockSerial::default();
+    assert_eq!(r5.identify(&mut serial, model_id(5)..=model_id(4)).unwrap(), None);
+    assert!(serial.written.is_empty());
+}
+
+#[test]
+fn template_index_occupancy() {
+    use index::{IndexChange, TemplateIndex};
+
+    let id = |id| constants::ModelId::new(id, 200).unwrap();
+    // Slots 0, 2 and 199, then slot 200, past the end of the library
+    let mut page = [0u8; 32];
+    page[0] = 0b0000_0101;
+    page[24] = 0b1000_0000;
+    page[25] = 0b0000_0001;
+    let reply = [ack(0x00, &sys_para(0x06)), ack(0x00, &page)].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let r5 = R503::new_with_address(0xFFFFFFFF);
+    let index = pollster::block_on(r5.read_template_index(&mut serial)).unwrap();
+    assert!(serial.to_read.is_empty());
+    assert_eq!(index.capacity(), 200);
+    assert!(index.is_occupied(id(2)));
+    assert!(!index.is_occupied(id(1)));
+    assert_eq!(index.iter_occupied().map(u16::from).collect::<Vec<_>>(), [0, 2, 199]);
+    assert_eq!(index.first_free(), Some(id(1)));
+    assert_eq!(index.count(), 3);
+
+    let mut old = [0u8; 32];
+    old[0] = 0b0000_0011;
+    let previous = TemplateIndex::from_pages(200, &[old]).unwrap();
+    assert_eq!(
+        index.diff(&previous).collect::<Vec<_>>(),
+        [
+            IndexChange::Removed(id(1)),
+            IndexChange::Added(id(2)),
+            IndexChange::Added(id(199)),
+        ]
+    );
+
+    // Larger libraries span more pages
+    let mut params = sys_para(0x06);
+    params[4..6].copy_from_slice(&300u16.to_be_bytes());
+    let reply = [ack(0x00, &params), ack(0x00, &[0xFF; 32]), ack(0x00, &[0xFF; 32])].concat();
+    let mut serial = MockSerial::with_reply(&reply);
+    let index = blocking::R503::new_with_address(0xFFFFFFFF).read_template_index(&mut serial).unwrap();
+    assert_eq!(index.count(), 300);
+    assert_eq!(index.first_free(), None);
+
+    // The index table stops at four pages
+    assert_eq!(TemplateIndex::from_pages(index::MAX_SLOTS, &[]).unwrap().first_free(), Some(id(0)));
+    assert!(TemplateIndex::from_pages(index::MAX_SLOTS + 1, &[]).is_none());
+    params[4..6].copy_from_slice(&1500u16.to_be_bytes());
+    let mut serial = MockSerial::with_reply(&ack(0x00, &params));
+    let res = pollster::block_on(r5.read_template_index(&mut serial));
+    assert!(matches!(res, Err(Error::BufferTooSmall { needed: 1500, available: 1024 })));
+    assert_eq!(serial.written.len(), 12);
+}